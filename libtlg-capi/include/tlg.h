@@ -0,0 +1,316 @@
+#ifndef LIBTLG_RS_TLG_H
+#define LIBTLG_RS_TLG_H
+
+/* Generated by cbindgen from libtlg-capi. Do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Result of a call
+ */
+typedef enum TlgStatus {
+  /**
+   * Success
+   */
+  TLG_STATUS_OK = 0,
+  /**
+   * IO error
+   */
+  TLG_STATUS_IO,
+  /**
+   * Invalid or truncated TLG data
+   */
+  TLG_STATUS_INVALID_FORMAT,
+  /**
+   * Unsupported color type, compression method or filter
+   */
+  TLG_STATUS_UNSUPPORTED,
+  /**
+   * A decode limit was exceeded
+   */
+  TLG_STATUS_LIMIT_EXCEEDED,
+  /**
+   * Encoding failed
+   */
+  TLG_STATUS_ENCODE,
+  /**
+   * Null pointer or bad enum value
+   */
+  TLG_STATUS_INVALID_ARGUMENT,
+  /**
+   * Index out of range
+   */
+  TLG_STATUS_OUT_OF_RANGE,
+  /**
+   * Internal panic
+   */
+  TLG_STATUS_PANIC,
+  /**
+   * Any other error, such as a buffer too small
+   */
+  TLG_STATUS_OTHER,
+} TlgStatus;
+
+/**
+ * Color type of a TLG image, valued by its number of channels
+ */
+typedef enum TlgColor {
+  /**
+   * Grayscale 8-bit
+   */
+  TLG_COLOR_GRAYSCALE8 = 1,
+  /**
+   * BGR 8-bit
+   */
+  TLG_COLOR_BGR24 = 3,
+  /**
+   * BGRA 8-bit
+   */
+  TLG_COLOR_BGRA32 = 4,
+} TlgColor;
+
+/**
+ * Pixel layout of decoded image data
+ */
+typedef enum TlgPixelFormat {
+  /**
+   * Layout of the color type of the image: grayscale, BGR or BGRA
+   */
+  TLG_PIXEL_FORMAT_NATIVE = 0,
+  /**
+   * RGBA 8-bit
+   */
+  TLG_PIXEL_FORMAT_RGBA8,
+  /**
+   * RGB 8-bit, dropping alpha
+   */
+  TLG_PIXEL_FORMAT_RGB8,
+  /**
+   * BGRA 8-bit
+   */
+  TLG_PIXEL_FORMAT_BGRA8,
+  /**
+   * BGRX 8-bit, with the unused byte set to 255
+   */
+  TLG_PIXEL_FORMAT_BGRX8,
+  /**
+   * RGBA 8-bit with color premultiplied by alpha
+   */
+  TLG_PIXEL_FORMAT_RGBA8_PREMULTIPLIED,
+  /**
+   * Grayscale and alpha 8-bit
+   */
+  TLG_PIXEL_FORMAT_GRAY_ALPHA8,
+} TlgPixelFormat;
+
+/**
+ * Decoded or user-built image, with its tags.
+ */
+typedef struct TlgImage TlgImage;
+
+/**
+ * Image information
+ */
+typedef struct TlgImageInfo {
+  /**
+   * TLG version: 5 or 6
+   */
+  uint32_t version;
+  /**
+   * Image width
+   */
+  uint32_t width;
+  /**
+   * Image height
+   */
+  uint32_t height;
+  /**
+   * Color type
+   */
+  enum TlgColor color;
+} TlgImageInfo;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Message of the last error on the calling thread, or null if no call
+ * failed yet.
+ *
+ * The string stays valid until the next failing call on the same thread.
+ */
+const char *tlg_last_error(void);
+
+/**
+ * Read the information of a TLG image without decoding pixel data.
+ *
+ * # Safety
+ *
+ * `data` must point to `len` readable bytes and `info` to a writable
+ * [`TlgImageInfo`].
+ */
+enum TlgStatus tlg_probe(const uint8_t *data, size_t len, struct TlgImageInfo *info);
+
+/**
+ * Decode a TLG image into a new [`TlgImage`].
+ *
+ * `format` is a [`TlgPixelFormat`] value giving the layout of the image
+ * data. The image must be freed with [`tlg_image_free`].
+ *
+ * # Safety
+ *
+ * `data` must point to `len` readable bytes and `out` to a writable
+ * pointer.
+ */
+enum TlgStatus tlg_decode(const uint8_t *data, size_t len, uint32_t format, struct TlgImage **out);
+
+/**
+ * Decode a TLG image into a caller-provided buffer.
+ *
+ * Rows are written `stride` bytes apart in the [`TlgPixelFormat`] given by
+ * `format`. `info` may be null.
+ *
+ * # Safety
+ *
+ * `data` must point to `len` readable bytes, `buf` to `buf_len` writable
+ * bytes and `info`, if not null, to a writable [`TlgImageInfo`].
+ */
+enum TlgStatus tlg_decode_into(const uint8_t *data,
+                               size_t len,
+                               uint32_t format,
+                               uint8_t *buf,
+                               size_t buf_len,
+                               size_t stride,
+                               struct TlgImageInfo *info);
+
+/**
+ * Create an image from pixel data in the native layout of `color`, a
+ * [`TlgColor`] value, to be encoded with [`tlg_encode`].
+ *
+ * The data is copied. The image must be freed with [`tlg_image_free`].
+ *
+ * # Safety
+ *
+ * `data` must point to `len` readable bytes and `out` to a writable
+ * pointer.
+ */
+enum TlgStatus tlg_image_new(uint32_t width,
+                             uint32_t height,
+                             uint32_t color,
+                             const uint8_t *data,
+                             size_t len,
+                             struct TlgImage **out);
+
+/**
+ * Free an image. Does nothing if `img` is null.
+ *
+ * # Safety
+ *
+ * `img` must be null or returned by this library and not freed yet.
+ */
+void tlg_image_free(struct TlgImage *img);
+
+/**
+ * Get the information of an image.
+ *
+ * # Safety
+ *
+ * `img` must be a valid image and `info` point to a writable
+ * [`TlgImageInfo`].
+ */
+enum TlgStatus tlg_image_info(const struct TlgImage *img, struct TlgImageInfo *info);
+
+/**
+ * Pixel data of an image, valid until the image is freed. Its size is
+ * stored in `len`.
+ *
+ * The data is in the [`TlgPixelFormat`] the image was decoded with, or in
+ * the native layout of its color type for an image built with
+ * [`tlg_image_new`].
+ *
+ * Returns null if `img` or `len` is null.
+ *
+ * # Safety
+ *
+ * `img` must be null or a valid image, and `len` null or writable.
+ */
+const uint8_t *tlg_image_data(const struct TlgImage *img, size_t *len);
+
+/**
+ * Number of tags of an image, including duplicate keys. Returns 0 if `img`
+ * is null.
+ *
+ * # Safety
+ *
+ * `img` must be null or a valid image.
+ */
+size_t tlg_image_tag_count(const struct TlgImage *img);
+
+/**
+ * Get the tag at `index`, in file order.
+ *
+ * The key and value are not null-terminated and stay valid until the tags
+ * are changed or the image is freed.
+ *
+ * # Safety
+ *
+ * `img` must be a valid image and the other pointers writable.
+ */
+enum TlgStatus tlg_image_tag(const struct TlgImage *img,
+                             size_t index,
+                             const uint8_t **key,
+                             size_t *key_len,
+                             const uint8_t **value,
+                             size_t *value_len);
+
+/**
+ * Append a tag to an image, even if the key is already present.
+ *
+ * # Safety
+ *
+ * `img` must be a valid image, `key` point to `key_len` readable bytes and
+ * `value` to `value_len` readable bytes.
+ */
+enum TlgStatus tlg_image_append_tag(struct TlgImage *img,
+                                    const uint8_t *key,
+                                    size_t key_len,
+                                    const uint8_t *value,
+                                    size_t value_len);
+
+/**
+ * Encode an image as TLG `version` 5 or 6.
+ *
+ * The image data must be in the native layout of its color type: an image
+ * decoded with another [`TlgPixelFormat`] fails with
+ * [`TlgStatus::InvalidArgument`]. The encoded bytes are stored in a new
+ * buffer, which must be freed with [`tlg_buffer_free`].
+ *
+ * # Safety
+ *
+ * `img` must be a valid image and `out`, `out_len` writable.
+ */
+enum TlgStatus tlg_encode(const struct TlgImage *img,
+                          uint32_t version,
+                          uint8_t **out,
+                          size_t *out_len);
+
+/**
+ * Free a buffer returned by [`tlg_encode`]. Does nothing if `buf` is null.
+ *
+ * # Safety
+ *
+ * `buf` must be null or returned by [`tlg_encode`] with size `len`, and
+ * not freed yet.
+ */
+void tlg_buffer_free(uint8_t *buf, size_t len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* LIBTLG_RS_TLG_H */
@@ -0,0 +1,541 @@
+//! C API of libtlg-rs.
+//!
+//! The header `include/tlg.h` is generated with the `cbindgen` CLI. After
+//! changing the API, regenerate it from this directory with
+//! `cbindgen --config cbindgen.toml --output include/tlg.h`.
+//!
+//! Functions returning [`TlgStatus`] record a message for any error, which
+//! can be read with [`tlg_last_error`]. Panics are caught and reported as
+//! [`TlgStatus::Panic`].
+use libtlg_rs::{DecodeOptions, PixelFormat, Tlg, TlgColorType, TlgError, TlgTags};
+use std::cell::RefCell;
+use std::ffi::{CString, c_char};
+use std::io::Cursor;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+
+/// Result of a call
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlgStatus {
+    /// Success
+    Ok = 0,
+    /// IO error
+    Io,
+    /// Invalid or truncated TLG data
+    InvalidFormat,
+    /// Unsupported color type, compression method or filter
+    Unsupported,
+    /// A decode limit was exceeded
+    LimitExceeded,
+    /// Encoding failed
+    Encode,
+    /// Null pointer or bad enum value
+    InvalidArgument,
+    /// Index out of range
+    OutOfRange,
+    /// Internal panic
+    Panic,
+    /// Any other error, such as a buffer too small
+    Other,
+}
+
+/// Color type of a TLG image, valued by its number of channels
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlgColor {
+    /// Grayscale 8-bit
+    Grayscale8 = 1,
+    /// BGR 8-bit
+    Bgr24 = 3,
+    /// BGRA 8-bit
+    Bgra32 = 4,
+}
+
+/// Pixel layout of decoded image data
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlgPixelFormat {
+    /// Layout of the color type of the image: grayscale, BGR or BGRA
+    Native = 0,
+    /// RGBA 8-bit
+    Rgba8,
+    /// RGB 8-bit, dropping alpha
+    Rgb8,
+    /// BGRA 8-bit
+    Bgra8,
+    /// BGRX 8-bit, with the unused byte set to 255
+    Bgrx8,
+    /// RGBA 8-bit with color premultiplied by alpha
+    Rgba8Premultiplied,
+    /// Grayscale and alpha 8-bit
+    GrayAlpha8,
+}
+
+/// Image information
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TlgImageInfo {
+    /// TLG version: 5 or 6
+    pub version: u32,
+    /// Image width
+    pub width: u32,
+    /// Image height
+    pub height: u32,
+    /// Color type
+    pub color: TlgColor,
+}
+
+/// Decoded or user-built image, with its tags.
+pub struct TlgImage {
+    tlg: Tlg,
+    /// Layout of the image data
+    pixel_format: PixelFormat,
+}
+
+enum Error {
+    Tlg(TlgError),
+    Arg(&'static str),
+    Range,
+}
+
+impl From<TlgError> for Error {
+    fn from(err: TlgError) -> Self {
+        Error::Tlg(err)
+    }
+}
+
+impl Error {
+    fn status(&self) -> TlgStatus {
+        match self {
+            Error::Tlg(err) => match err {
+                TlgError::Io(_) => TlgStatus::Io,
+                TlgError::InvalidFormat
+                | TlgError::UnexpectedEof
+                | TlgError::IndexOutOfRange
+                | TlgError::InvalidTagValue(_) => TlgStatus::InvalidFormat,
+                TlgError::UnsupportedColorType(_)
+                | TlgError::UnsupportedCompressedMethod(_)
+                | TlgError::UnsupportedDataFlags(_)
+                | TlgError::UnsupportedColorTypes(_)
+                | TlgError::UnsupportedFilterType(_)
+                | TlgError::UnsupportedGolombTable => TlgStatus::Unsupported,
+                TlgError::LimitExceeded(_) => TlgStatus::LimitExceeded,
+                TlgError::EncodeError(_) => TlgStatus::Encode,
+                TlgError::Str(_) => TlgStatus::Other,
+            },
+            Error::Arg(_) => TlgStatus::InvalidArgument,
+            Error::Range => TlgStatus::OutOfRange,
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            Error::Tlg(err) => err.to_string(),
+            Error::Arg(msg) => msg.to_string(),
+            Error::Range => "Index out of range".to_string(),
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(msg: String) {
+    let msg = CString::new(msg.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(msg));
+}
+
+/// Run `f`, turning errors and panics into a status.
+fn ffi_call<F: FnOnce() -> Result<(), Error>>(f: F) -> TlgStatus {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => TlgStatus::Ok,
+        Ok(Err(err)) => {
+            set_last_error(err.message());
+            err.status()
+        }
+        Err(_) => {
+            set_last_error("Internal panic".to_string());
+            TlgStatus::Panic
+        }
+    }
+}
+
+/// Borrow `len` bytes at `ptr`, allowing a null pointer when `len` is 0.
+unsafe fn slice<'a>(ptr: *const u8, len: usize) -> Result<&'a [u8], Error> {
+    if len == 0 {
+        Ok(&[])
+    } else if ptr.is_null() {
+        Err(Error::Arg("Null data pointer"))
+    } else {
+        Ok(unsafe { std::slice::from_raw_parts(ptr, len) })
+    }
+}
+
+unsafe fn out_ref<'a, T>(ptr: *mut T) -> Result<&'a mut T, Error> {
+    unsafe { ptr.as_mut() }.ok_or(Error::Arg("Null output pointer"))
+}
+
+unsafe fn image_ref<'a>(img: *const TlgImage) -> Result<&'a TlgImage, Error> {
+    unsafe { img.as_ref() }.ok_or(Error::Arg("Null image pointer"))
+}
+
+fn color_from_tlg(color: TlgColorType) -> TlgColor {
+    match color {
+        TlgColorType::Grayscale8 => TlgColor::Grayscale8,
+        TlgColorType::Bgr24 => TlgColor::Bgr24,
+        TlgColorType::Bgra32 => TlgColor::Bgra32,
+    }
+}
+
+fn color_to_tlg(color: u32) -> Result<TlgColorType, Error> {
+    [
+        TlgColorType::Grayscale8,
+        TlgColorType::Bgr24,
+        TlgColorType::Bgra32,
+    ]
+    .into_iter()
+    .find(|c| color_from_tlg(*c) as u32 == color)
+    .ok_or(Error::Arg("Invalid color type"))
+}
+
+const PIXEL_FORMATS: [(TlgPixelFormat, PixelFormat); 7] = [
+    (TlgPixelFormat::Native, PixelFormat::Native),
+    (TlgPixelFormat::Rgba8, PixelFormat::Rgba8),
+    (TlgPixelFormat::Rgb8, PixelFormat::Rgb8),
+    (TlgPixelFormat::Bgra8, PixelFormat::Bgra8),
+    (TlgPixelFormat::Bgrx8, PixelFormat::Bgrx8),
+    (
+        TlgPixelFormat::Rgba8Premultiplied,
+        PixelFormat::Rgba8Premultiplied,
+    ),
+    (TlgPixelFormat::GrayAlpha8, PixelFormat::GrayAlpha8),
+];
+
+fn pixel_format(format: u32) -> Result<PixelFormat, Error> {
+    PIXEL_FORMATS
+        .iter()
+        .find(|(f, _)| *f as u32 == format)
+        .map(|(_, f)| *f)
+        .ok_or(Error::Arg("Invalid pixel format"))
+}
+
+fn image_info(version: u32, width: u32, height: u32, color: TlgColorType) -> TlgImageInfo {
+    TlgImageInfo {
+        version,
+        width,
+        height,
+        color: color_from_tlg(color),
+    }
+}
+
+/// Message of the last error on the calling thread, or null if no call
+/// failed yet.
+///
+/// The string stays valid until the next failing call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn tlg_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(std::ptr::null(), |s| s.as_ptr()))
+}
+
+/// Read the information of a TLG image without decoding pixel data.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes and `info` to a writable
+/// [`TlgImageInfo`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tlg_probe(
+    data: *const u8,
+    len: usize,
+    info: *mut TlgImageInfo,
+) -> TlgStatus {
+    ffi_call(|| {
+        let data = unsafe { slice(data, len) }?;
+        let info = unsafe { out_ref(info) }?;
+        let i = libtlg_rs::probe_tlg(Cursor::new(data))?;
+        *info = image_info(i.version, i.width, i.height, i.color);
+        Ok(())
+    })
+}
+
+/// Decode a TLG image into a new [`TlgImage`].
+///
+/// `format` is a [`TlgPixelFormat`] value giving the layout of the image
+/// data. The image must be freed with [`tlg_image_free`].
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes and `out` to a writable
+/// pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tlg_decode(
+    data: *const u8,
+    len: usize,
+    format: u32,
+    out: *mut *mut TlgImage,
+) -> TlgStatus {
+    ffi_call(|| {
+        let data = unsafe { slice(data, len) }?;
+        let out = unsafe { out_ref(out) }?;
+        let options = DecodeOptions {
+            pixel_format: pixel_format(format)?,
+            ..Default::default()
+        };
+        let tlg = libtlg_rs::load_tlg_with_options(Cursor::new(data), &options)?;
+        *out = Box::into_raw(Box::new(TlgImage {
+            tlg,
+            pixel_format: options.pixel_format,
+        }));
+        Ok(())
+    })
+}
+
+/// Decode a TLG image into a caller-provided buffer.
+///
+/// Rows are written `stride` bytes apart in the [`TlgPixelFormat`] given by
+/// `format`. `info` may be null.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes, `buf` to `buf_len` writable
+/// bytes and `info`, if not null, to a writable [`TlgImageInfo`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tlg_decode_into(
+    data: *const u8,
+    len: usize,
+    format: u32,
+    buf: *mut u8,
+    buf_len: usize,
+    stride: usize,
+    info: *mut TlgImageInfo,
+) -> TlgStatus {
+    ffi_call(|| {
+        let data = unsafe { slice(data, len) }?;
+        if buf.is_null() {
+            return Err(Error::Arg("Null buffer pointer"));
+        }
+        let buf = unsafe { std::slice::from_raw_parts_mut(buf, buf_len) };
+        let options = DecodeOptions {
+            pixel_format: pixel_format(format)?,
+            ..Default::default()
+        };
+        let i = libtlg_rs::load_tlg_into_with_options(Cursor::new(data), buf, stride, &options)?;
+        if let Some(info) = unsafe { info.as_mut() } {
+            *info = image_info(i.version, i.width, i.height, i.color);
+        }
+        Ok(())
+    })
+}
+
+/// Create an image from pixel data in the native layout of `color`, a
+/// [`TlgColor`] value, to be encoded with [`tlg_encode`].
+///
+/// The data is copied. The image must be freed with [`tlg_image_free`].
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes and `out` to a writable
+/// pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tlg_image_new(
+    width: u32,
+    height: u32,
+    color: u32,
+    data: *const u8,
+    len: usize,
+    out: *mut *mut TlgImage,
+) -> TlgStatus {
+    ffi_call(|| {
+        let data = unsafe { slice(data, len) }?;
+        let out = unsafe { out_ref(out) }?;
+        let tlg = Tlg {
+            tags: TlgTags::new(),
+            chunks: Vec::new(),
+            version: 5,
+            width,
+            height,
+            color: color_to_tlg(color)?,
+            data: data.to_vec(),
+            tlg5_layout: None,
+        };
+        *out = Box::into_raw(Box::new(TlgImage {
+            tlg,
+            pixel_format: PixelFormat::Native,
+        }));
+        Ok(())
+    })
+}
+
+/// Free an image. Does nothing if `img` is null.
+///
+/// # Safety
+///
+/// `img` must be null or returned by this library and not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tlg_image_free(img: *mut TlgImage) {
+    if !img.is_null() {
+        drop(unsafe { Box::from_raw(img) });
+    }
+}
+
+/// Get the information of an image.
+///
+/// # Safety
+///
+/// `img` must be a valid image and `info` point to a writable
+/// [`TlgImageInfo`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tlg_image_info(
+    img: *const TlgImage,
+    info: *mut TlgImageInfo,
+) -> TlgStatus {
+    ffi_call(|| {
+        let tlg = &unsafe { image_ref(img) }?.tlg;
+        let info = unsafe { out_ref(info) }?;
+        *info = image_info(tlg.version, tlg.width, tlg.height, tlg.color);
+        Ok(())
+    })
+}
+
+/// Pixel data of an image, valid until the image is freed. Its size is
+/// stored in `len`.
+///
+/// The data is in the [`TlgPixelFormat`] the image was decoded with, or in
+/// the native layout of its color type for an image built with
+/// [`tlg_image_new`].
+///
+/// Returns null if `img` or `len` is null.
+///
+/// # Safety
+///
+/// `img` must be null or a valid image, and `len` null or writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tlg_image_data(img: *const TlgImage, len: *mut usize) -> *const u8 {
+    match unsafe { (img.as_ref(), len.as_mut()) } {
+        (Some(img), Some(len)) => {
+            *len = img.tlg.data.len();
+            img.tlg.data.as_ptr()
+        }
+        _ => std::ptr::null(),
+    }
+}
+
+/// Number of tags of an image, including duplicate keys. Returns 0 if `img`
+/// is null.
+///
+/// # Safety
+///
+/// `img` must be null or a valid image.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tlg_image_tag_count(img: *const TlgImage) -> usize {
+    unsafe { img.as_ref() }.map_or(0, |img| img.tlg.tags.len())
+}
+
+/// Get the tag at `index`, in file order.
+///
+/// The key and value are not null-terminated and stay valid until the tags
+/// are changed or the image is freed.
+///
+/// # Safety
+///
+/// `img` must be a valid image and the other pointers writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tlg_image_tag(
+    img: *const TlgImage,
+    index: usize,
+    key: *mut *const u8,
+    key_len: *mut usize,
+    value: *mut *const u8,
+    value_len: *mut usize,
+) -> TlgStatus {
+    ffi_call(|| {
+        let tlg = &unsafe { image_ref(img) }?.tlg;
+        let (key, key_len) = unsafe { (out_ref(key)?, out_ref(key_len)?) };
+        let (value, value_len) = unsafe { (out_ref(value)?, out_ref(value_len)?) };
+        let (k, v) = tlg.tags.iter().nth(index).ok_or(Error::Range)?;
+        *key = k.as_ptr();
+        *key_len = k.len();
+        *value = v.as_ptr();
+        *value_len = v.len();
+        Ok(())
+    })
+}
+
+/// Append a tag to an image, even if the key is already present.
+///
+/// # Safety
+///
+/// `img` must be a valid image, `key` point to `key_len` readable bytes and
+/// `value` to `value_len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tlg_image_append_tag(
+    img: *mut TlgImage,
+    key: *const u8,
+    key_len: usize,
+    value: *const u8,
+    value_len: usize,
+) -> TlgStatus {
+    ffi_call(|| {
+        let tlg = unsafe { img.as_mut() }.ok_or(Error::Arg("Null image pointer"))?;
+        let key = unsafe { slice(key, key_len) }?;
+        let value = unsafe { slice(value, value_len) }?;
+        tlg.tlg.tags.append(key, value);
+        Ok(())
+    })
+}
+
+/// Encode an image as TLG `version` 5 or 6.
+///
+/// The image data must be in the native layout of its color type: an image
+/// decoded with another [`TlgPixelFormat`] fails with
+/// [`TlgStatus::InvalidArgument`]. The encoded bytes are stored in a new
+/// buffer, which must be freed with [`tlg_buffer_free`].
+///
+/// # Safety
+///
+/// `img` must be a valid image and `out`, `out_len` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tlg_encode(
+    img: *const TlgImage,
+    version: u32,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> TlgStatus {
+    ffi_call(|| {
+        let img = unsafe { image_ref(img) }?;
+        let (out, out_len) = unsafe { (out_ref(out)?, out_ref(out_len)?) };
+        if img.pixel_format != PixelFormat::Native {
+            return Err(Error::Arg("Image data is not in the native pixel format"));
+        }
+        let tlg = &img.tlg;
+        let mut buf = Vec::new();
+        if version == tlg.version {
+            libtlg_rs::save_tlg(tlg, &mut buf)?;
+        } else {
+            let tlg = Tlg {
+                version,
+                tlg5_layout: None,
+                ..tlg.clone()
+            };
+            libtlg_rs::save_tlg(&tlg, &mut buf)?;
+        }
+        let buf = Box::into_raw(buf.into_boxed_slice());
+        *out_len = buf.len();
+        *out = buf.cast();
+        Ok(())
+    })
+}
+
+/// Free a buffer returned by [`tlg_encode`]. Does nothing if `buf` is null.
+///
+/// # Safety
+///
+/// `buf` must be null or returned by [`tlg_encode`] with size `len`, and
+/// not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tlg_buffer_free(buf: *mut u8, len: usize) {
+    if !buf.is_null() {
+        drop(unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(buf, len)) });
+    }
+}
@@ -0,0 +1,133 @@
+//! Calls through the C API, as a C program would make them.
+use std::ptr;
+use tlg_capi::*;
+
+const WIDTH: u32 = 19;
+const HEIGHT: u32 = 11;
+
+/// BGRA pixels of a gradient.
+fn pixels() -> Vec<u8> {
+    (0..WIDTH * HEIGHT * 4)
+        .map(|i| (i * 7 % 251) as u8)
+        .collect()
+}
+
+fn new_image(data: &[u8]) -> *mut TlgImage {
+    let mut img = ptr::null_mut();
+    let status = unsafe {
+        tlg_image_new(
+            WIDTH,
+            HEIGHT,
+            TlgColor::Bgra32 as u32,
+            data.as_ptr(),
+            data.len(),
+            &mut img,
+        )
+    };
+    assert_eq!(status, TlgStatus::Ok);
+    img
+}
+
+/// Encode `img`, returning a copy of the encoded bytes.
+fn encode(img: *const TlgImage, version: u32) -> Result<Vec<u8>, TlgStatus> {
+    let mut out = ptr::null_mut();
+    let mut out_len = 0;
+    let status = unsafe { tlg_encode(img, version, &mut out, &mut out_len) };
+    if status != TlgStatus::Ok {
+        return Err(status);
+    }
+    let encoded = unsafe { std::slice::from_raw_parts(out, out_len) }.to_vec();
+    unsafe { tlg_buffer_free(out, out_len) };
+    Ok(encoded)
+}
+
+fn decode(data: &[u8], format: TlgPixelFormat) -> *mut TlgImage {
+    let mut img = ptr::null_mut();
+    let status = unsafe { tlg_decode(data.as_ptr(), data.len(), format as u32, &mut img) };
+    assert_eq!(status, TlgStatus::Ok);
+    img
+}
+
+fn image_data<'a>(img: *const TlgImage) -> &'a [u8] {
+    let mut len = 0;
+    let data = unsafe { tlg_image_data(img, &mut len) };
+    unsafe { std::slice::from_raw_parts(data, len) }
+}
+
+fn tag<'a>(img: *const TlgImage, index: usize) -> Result<(&'a [u8], &'a [u8]), TlgStatus> {
+    let (mut key, mut key_len) = (ptr::null(), 0);
+    let (mut value, mut value_len) = (ptr::null(), 0);
+    let status = unsafe {
+        tlg_image_tag(
+            img,
+            index,
+            &mut key,
+            &mut key_len,
+            &mut value,
+            &mut value_len,
+        )
+    };
+    if status != TlgStatus::Ok {
+        return Err(status);
+    }
+    unsafe {
+        Ok((
+            std::slice::from_raw_parts(key, key_len),
+            std::slice::from_raw_parts(value, value_len),
+        ))
+    }
+}
+
+#[test]
+fn round_trip() {
+    let data = pixels();
+    let img = new_image(&data);
+    let tags: [(&[u8], &[u8]); 2] = [(b"mode", b"alpha"), (b"mode", b"add")];
+    for (key, value) in tags {
+        let status = unsafe {
+            tlg_image_append_tag(img, key.as_ptr(), key.len(), value.as_ptr(), value.len())
+        };
+        assert_eq!(status, TlgStatus::Ok);
+    }
+
+    for version in [5, 6] {
+        let encoded = encode(img, version).unwrap();
+        let mut info = TlgImageInfo {
+            version: 0,
+            width: 0,
+            height: 0,
+            color: TlgColor::Grayscale8,
+        };
+        let status = unsafe { tlg_probe(encoded.as_ptr(), encoded.len(), &mut info) };
+        assert_eq!(status, TlgStatus::Ok);
+        assert_eq!(info.version, version);
+        assert_eq!((info.width, info.height), (WIDTH, HEIGHT));
+        assert_eq!(info.color, TlgColor::Bgra32);
+
+        let decoded = decode(&encoded, TlgPixelFormat::Native);
+        assert_eq!(image_data(decoded), data);
+        assert_eq!(unsafe { tlg_image_tag_count(decoded) }, 2);
+        assert_eq!(tag(decoded, 0), Ok(tags[0]));
+        assert_eq!(tag(decoded, 1), Ok(tags[1]));
+        assert_eq!(tag(decoded, 2), Err(TlgStatus::OutOfRange));
+        assert_eq!(encode(decoded, version).unwrap(), encoded);
+        unsafe { tlg_image_free(decoded) };
+    }
+    unsafe { tlg_image_free(img) };
+}
+
+#[test]
+fn encode_rejects_converted_data() {
+    let img = new_image(&pixels());
+    let encoded = encode(img, 5).unwrap();
+    unsafe { tlg_image_free(img) };
+    for format in [
+        TlgPixelFormat::Rgba8,
+        TlgPixelFormat::Bgrx8,
+        TlgPixelFormat::GrayAlpha8,
+    ] {
+        let decoded = decode(&encoded, format);
+        assert_eq!(encode(decoded, 5), Err(TlgStatus::InvalidArgument));
+        unsafe { tlg_image_free(decoded) };
+    }
+}
@@ -0,0 +1,174 @@
+//! Python bindings of libtlg-rs, imported as the `tlg` module.
+//!
+//! Pixel data uses the PIL modes `L`, `RGB` and `RGBA`, so an image can be
+//! passed to `PIL.Image.frombuffer(img.mode, img.size, img.data, "raw",
+//! img.mode, 0, 1)`.
+use libtlg_rs::{DecodeOptions, PixelFormat, Tlg, TlgColorType, TlgTags};
+use pyo3::create_exception;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+create_exception!(
+    tlg,
+    TlgError,
+    PyValueError,
+    "Error raised by the TLG codec."
+);
+
+fn to_py_err(err: libtlg_rs::TlgError) -> PyErr {
+    TlgError::new_err(err.to_string())
+}
+
+/// PIL mode of a TLG color type.
+fn pil_mode(color: TlgColorType) -> &'static str {
+    match color {
+        TlgColorType::Grayscale8 => "L",
+        TlgColorType::Bgr24 => "RGB",
+        TlgColorType::Bgra32 => "RGBA",
+    }
+}
+
+/// Decoded pixel format matching [`pil_mode`].
+fn pil_format(color: TlgColorType) -> PixelFormat {
+    match color {
+        TlgColorType::Grayscale8 => PixelFormat::Native,
+        TlgColorType::Bgr24 => PixelFormat::Rgb8,
+        TlgColorType::Bgra32 => PixelFormat::Rgba8,
+    }
+}
+
+/// Decoded TLG image.
+///
+/// `data` is in the layout of `mode`, one of `L`, `RGB` or `RGBA`.
+#[pyclass(name = "Image", module = "tlg", frozen)]
+struct Image {
+    tlg: Tlg,
+}
+
+#[pymethods]
+impl Image {
+    /// TLG version: 5 or 6
+    #[getter]
+    fn version(&self) -> u32 {
+        self.tlg.version
+    }
+
+    /// Image width
+    #[getter]
+    fn width(&self) -> u32 {
+        self.tlg.width
+    }
+
+    /// Image height
+    #[getter]
+    fn height(&self) -> u32 {
+        self.tlg.height
+    }
+
+    /// `(width, height)` tuple
+    #[getter]
+    fn size(&self) -> (u32, u32) {
+        (self.tlg.width, self.tlg.height)
+    }
+
+    /// PIL mode of `data`
+    #[getter]
+    fn mode(&self) -> &'static str {
+        pil_mode(self.tlg.color)
+    }
+
+    /// Tags as a list of `(key, value)` bytes pairs in file order
+    #[getter]
+    fn tags<'py>(&self, py: Python<'py>) -> Vec<(Bound<'py, PyBytes>, Bound<'py, PyBytes>)> {
+        self.tlg
+            .tags
+            .iter()
+            .map(|(k, v)| (PyBytes::new(py, k), PyBytes::new(py, v)))
+            .collect()
+    }
+
+    /// Pixel data
+    #[getter]
+    fn data<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.tlg.data)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "<tlg.Image version={} mode={} size={}x{}>",
+            self.tlg.version,
+            self.mode(),
+            self.tlg.width,
+            self.tlg.height
+        )
+    }
+}
+
+/// Decode a TLG image from bytes.
+#[pyfunction]
+fn load(py: Python<'_>, data: &[u8]) -> PyResult<Image> {
+    let tlg = py
+        .detach(|| {
+            let info = libtlg_rs::probe_tlg_from_slice(data)?;
+            let options = DecodeOptions {
+                pixel_format: pil_format(info.color),
+                ..Default::default()
+            };
+            libtlg_rs::load_tlg_from_slice(data, &options)
+        })
+        .map_err(to_py_err)?;
+    Ok(Image { tlg })
+}
+
+/// Encode pixel data in PIL mode `L`, `RGB` or `RGBA` as a TLG image.
+///
+/// `tags` is a list of `(key, value)` bytes pairs, such as `Image.tags`.
+/// `version` is 5 or 6.
+#[pyfunction]
+#[pyo3(signature = (mode, size, data, tags=None, version=5))]
+fn save<'py>(
+    py: Python<'py>,
+    mode: &str,
+    size: (u32, u32),
+    data: &[u8],
+    tags: Option<Vec<(Vec<u8>, Vec<u8>)>>,
+    version: u32,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let color = match mode {
+        "L" => TlgColorType::Grayscale8,
+        "RGB" => TlgColorType::Bgr24,
+        "RGBA" => TlgColorType::Bgra32,
+        _ => return Err(PyValueError::new_err(format!("Unsupported mode: {mode}"))),
+    };
+    let mut data = data.to_vec();
+    libtlg_rs::swap_red_blue(&mut data, color);
+    let tlg = Tlg {
+        tags: tags.into_iter().flatten().collect::<TlgTags>(),
+        chunks: Vec::new(),
+        version,
+        width: size.0,
+        height: size.1,
+        color,
+        data,
+        tlg5_layout: None,
+    };
+    let out = py
+        .detach(|| {
+            let mut out = Vec::new();
+            libtlg_rs::save_tlg(&tlg, &mut out).map(|_| out)
+        })
+        .map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &out))
+}
+
+/// Read and write TLG images.
+#[pymodule]
+#[pyo3(name = "tlg")]
+fn tlg_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Image>()?;
+    m.add("TlgError", m.py().get_type::<TlgError>())?;
+    m.add_function(wrap_pyfunction!(load, m)?)?;
+    m.add_function(wrap_pyfunction!(save, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,94 @@
+//! Decoding and encoding over tokio's asynchronous IO traits.
+use crate::*;
+use std::io::Cursor;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+#[cfg(feature = "encode")]
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// Run `f` on tokio's blocking thread pool, resuming its panic if it
+/// panicked.
+async fn blocking<R: Send + 'static>(f: impl FnOnce() -> Result<R> + Send + 'static) -> Result<R> {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(err) => match err.try_into_panic() {
+            Ok(panic) => std::panic::resume_unwind(panic),
+            Err(err) => Err(std::io::Error::from(err).into()),
+        },
+    }
+}
+
+/// Read the whole stream into memory, honoring
+/// [`DecodeLimits::max_alloc_bytes`].
+async fn read_all<T: AsyncRead + AsyncSeek + Unpin>(
+    src: &mut T,
+    limits: &DecodeLimits,
+) -> Result<Vec<u8>> {
+    src.rewind().await?;
+    let mut buf = Vec::new();
+    let limit = u64::try_from(limits.max_alloc_bytes).unwrap_or(u64::MAX);
+    src.take(limit.saturating_add(1))
+        .read_to_end(&mut buf)
+        .await?;
+    load_tlg::check_alloc(limits, buf.len())?;
+    Ok(buf)
+}
+
+/// Decode TLG image from an asynchronous reader
+///
+/// The stream is read into memory, then decoded as [`load_tlg`] does on
+/// tokio's blocking thread pool, so this must be called within a tokio
+/// runtime.
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+pub async fn load_tlg_async<T: AsyncRead + AsyncSeek + Unpin>(src: T) -> Result<Tlg> {
+    load_tlg_async_with_options(src, &DecodeOptions::default()).await
+}
+
+/// Decode TLG image from an asynchronous reader, using the given options.
+///
+/// Same as [`load_tlg_with_options`]. The size of the stream is checked
+/// against [`DecodeLimits::max_alloc_bytes`]. Decoding runs in
+/// [`tokio::task::spawn_blocking`].
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+pub async fn load_tlg_async_with_options<T: AsyncRead + AsyncSeek + Unpin>(
+    mut src: T,
+    options: &DecodeOptions,
+) -> Result<Tlg> {
+    let buf = read_all(&mut src, &options.limits).await?;
+    let options = options.clone();
+    blocking(move || load_tlg_with_options(Cursor::new(buf), &options)).await
+}
+
+/// Encode TLG image to an asynchronous writer
+///
+/// The image is copied and encoded in memory as [`save_tlg`] does on tokio's
+/// blocking thread pool, so this must be called within a tokio runtime. The
+/// encoded data is then written and flushed.
+#[cfg(feature = "encode")]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "async", feature = "encode"))))]
+pub async fn save_tlg_async<W: AsyncWrite + Unpin>(img: &Tlg, writer: W) -> Result<()> {
+    save_tlg_async_with_options(img, writer, &EncodeOptions::default()).await
+}
+
+/// Encode TLG image to an asynchronous writer with the given options
+///
+/// Same as [`save_tlg_with_options`]. Encoding runs in
+/// [`tokio::task::spawn_blocking`].
+#[cfg(feature = "encode")]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "async", feature = "encode"))))]
+pub async fn save_tlg_async_with_options<W: AsyncWrite + Unpin>(
+    img: &Tlg,
+    mut writer: W,
+    options: &EncodeOptions,
+) -> Result<()> {
+    let img = img.clone();
+    let options = options.clone();
+    let buf = blocking(move || {
+        let mut buf = Vec::new();
+        save_tlg_with_options(&img, &mut buf, &options)?;
+        Ok(buf)
+    })
+    .await?;
+    writer.write_all(&buf).await?;
+    writer.flush().await?;
+    Ok(())
+}
@@ -0,0 +1,209 @@
+use crate::load_tlg::{StripeDecoder, open_tlg};
+use crate::*;
+
+/// Incremental TLG decoder.
+///
+/// Rows are decoded on demand, one TLG5 block or TLG6 stripe at a time, so
+/// only a few rows of the image are kept in memory.
+///
+/// ```no_run
+/// # fn main() -> libtlg_rs::Result<()> {
+/// let file = std::io::BufReader::new(std::fs::File::open("image.tlg")?);
+/// let mut decoder = libtlg_rs::TlgDecoder::new(file)?;
+/// while let Some(row) = decoder.next_row()? {
+///     // process the row
+/// #   let _ = row;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct TlgDecoder<T> {
+    src: T,
+    info: TlgInfo,
+    pixel_format: PixelFormat,
+    inner: StripeDecoder,
+    /// Decoded rows of the current stripe
+    stripe: Vec<u8>,
+    /// Number of rows in [`Self::stripe`]
+    stripe_rows: u32,
+    /// Number of rows in [`Self::stripe`] already returned
+    stripe_pos: u32,
+    /// Number of rows already returned
+    row: u32,
+    failed: bool,
+}
+
+impl<T: Read + Seek> TlgDecoder<T> {
+    /// Create a decoder, reading the headers and tags of the image.
+    pub fn new(src: T) -> Result<Self> {
+        Self::with_options(src, &DecodeOptions::default())
+    }
+
+    /// Create a decoder with the given options, reading the headers and tags
+    /// of the image.
+    pub fn with_options(mut src: T, options: &DecodeOptions) -> Result<Self> {
+        let (info, inner) = open_tlg(&mut src, options, false)?;
+        let row_size = info.width as usize * options.pixel_format.bytes_per_pixel(info.color);
+        let stripe_size = row_size * inner.stripe_height().min(info.height) as usize;
+        crate::load_tlg::check_alloc(&options.limits, stripe_size)?;
+        let stripe = vec![0u8; stripe_size];
+        Ok(Self {
+            src,
+            info,
+            pixel_format: options.pixel_format,
+            inner,
+            stripe,
+            stripe_rows: 0,
+            stripe_pos: 0,
+            row: 0,
+            failed: false,
+        })
+    }
+
+    /// Image information
+    pub fn info(&self) -> &TlgInfo {
+        &self.info
+    }
+
+    /// Size of a decoded row in bytes.
+    pub fn row_size(&self) -> usize {
+        self.info.width as usize * self.pixel_format.bytes_per_pixel(self.info.color)
+    }
+
+    /// Number of rows already returned.
+    pub fn current_row(&self) -> u32 {
+        self.row
+    }
+
+    /// Number of rows not yet returned.
+    pub fn remaining_rows(&self) -> u32 {
+        self.info.height - self.row
+    }
+
+    fn fill_stripe(&mut self) -> Result<bool> {
+        if self.stripe_pos < self.stripe_rows {
+            return Ok(true);
+        }
+        if self.failed {
+            return Err(TlgError::Str("Decoder is in a failed state".to_string()));
+        }
+        if self.row >= self.info.height {
+            return Ok(false);
+        }
+        let row_size = self.row_size();
+        match self
+            .inner
+            .decode_stripe(&mut self.src, &mut self.stripe, row_size)
+        {
+            Ok(rows) => {
+                self.stripe_rows = rows;
+                self.stripe_pos = 0;
+                Ok(true)
+            }
+            Err(e) => {
+                self.failed = true;
+                Err(e)
+            }
+        }
+    }
+
+    /// Decode the next row.
+    ///
+    /// Returns `None` once all rows have been returned.
+    pub fn next_row(&mut self) -> Result<Option<&[u8]>> {
+        if !self.fill_stripe()? {
+            return Ok(None);
+        }
+        let row_size = self.row_size();
+        let start = self.stripe_pos as usize * row_size;
+        self.stripe_pos += 1;
+        self.row += 1;
+        Ok(Some(&self.stripe[start..start + row_size]))
+    }
+
+    /// Decode the rest of the current stripe.
+    ///
+    /// A stripe is a TLG5 block or 8 rows of a TLG6 image. If rows of the
+    /// stripe were already returned, only the remaining rows are returned.
+    /// Returns `None` once all rows have been returned.
+    pub fn next_stripe(&mut self) -> Result<Option<&[u8]>> {
+        if !self.fill_stripe()? {
+            return Ok(None);
+        }
+        let row_size = self.row_size();
+        let start = self.stripe_pos as usize * row_size;
+        let end = self.stripe_rows as usize * row_size;
+        self.row += self.stripe_rows - self.stripe_pos;
+        self.stripe_pos = self.stripe_rows;
+        Ok(Some(&self.stripe[start..end]))
+    }
+
+    /// Decode as many whole rows as fit in `buf`.
+    ///
+    /// Returns the number of rows written, which is 0 once all rows have
+    /// been returned. Fails if rows remain and `buf` is smaller than a row.
+    pub fn read_rows(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let row_size = self.row_size();
+        if buf.len() < row_size && self.row < self.info.height {
+            return Err(TlgError::Str(format!(
+                "Buffer of {} bytes is too small, {row_size} bytes are needed",
+                buf.len()
+            )));
+        }
+        let capacity = buf.len() / row_size;
+        let mut rows = 0;
+        while rows < capacity && self.row < self.info.height {
+            let out = &mut buf[rows * row_size..];
+            if self.stripe_pos < self.stripe_rows {
+                let count = ((self.stripe_rows - self.stripe_pos) as usize).min(capacity - rows);
+                let start = self.stripe_pos as usize * row_size;
+                out[..count * row_size]
+                    .copy_from_slice(&self.stripe[start..start + count * row_size]);
+                self.stripe_pos += count as u32;
+                self.row += count as u32;
+                rows += count;
+            } else if capacity - rows
+                >= self.inner.stripe_height().min(self.remaining_rows()) as usize
+                && !self.failed
+            {
+                // Decode the whole stripe in place.
+                match self.inner.decode_stripe(&mut self.src, out, row_size) {
+                    Ok(count) => {
+                        self.row += count;
+                        rows += count as usize;
+                    }
+                    Err(e) => {
+                        self.failed = true;
+                        return Err(e);
+                    }
+                }
+            } else {
+                self.fill_stripe()?;
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Consume the decoder, returning the underlying reader.
+    pub fn into_inner(self) -> T {
+        self.src
+    }
+
+    /// Consume the decoder, returning the image information.
+    pub fn into_info(self) -> TlgInfo {
+        self.info
+    }
+}
+
+impl<T: Read + Seek> Iterator for TlgDecoder<T> {
+    type Item = Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed && self.stripe_pos >= self.stripe_rows {
+            return None;
+        }
+        self.next_row()
+            .map(|row| row.map(|r| r.to_vec()))
+            .transpose()
+    }
+}
@@ -0,0 +1,218 @@
+//! Integration with the `image` crate.
+use crate::*;
+use image::error::{DecodingError, ImageFormatHint, LimitError, LimitErrorKind};
+#[cfg(feature = "encode")]
+use image::error::{EncodingError, UnsupportedError, UnsupportedErrorKind};
+use image::{ColorType, DynamicImage, ImageDecoder, ImageError, ImageResult};
+#[cfg(feature = "encode")]
+use image::{ExtendedColorType, ImageEncoder};
+#[cfg(feature = "encode")]
+use std::io::Write;
+
+fn format_hint() -> ImageFormatHint {
+    ImageFormatHint::Name("TLG".to_string())
+}
+
+impl From<TlgError> for ImageError {
+    fn from(err: TlgError) -> Self {
+        match err {
+            TlgError::Io(e) => ImageError::IoError(e),
+            TlgError::LimitExceeded(_) => {
+                ImageError::Limits(LimitError::from_kind(LimitErrorKind::InsufficientMemory))
+            }
+            #[cfg(feature = "encode")]
+            TlgError::EncodeError(_) => {
+                ImageError::Encoding(EncodingError::new(format_hint(), err))
+            }
+            err => ImageError::Decoding(DecodingError::new(format_hint(), err)),
+        }
+    }
+}
+
+/// Pixel format and `image` color type used to decode a TLG color type.
+fn decode_format(color: TlgColorType) -> (PixelFormat, ColorType) {
+    match color {
+        TlgColorType::Grayscale8 => (PixelFormat::Native, ColorType::L8),
+        TlgColorType::Bgr24 => (PixelFormat::Rgb8, ColorType::Rgb8),
+        TlgColorType::Bgra32 => (PixelFormat::Rgba8, ColorType::Rgba8),
+    }
+}
+
+/// [`ImageDecoder`] for TLG images.
+///
+/// Images are decoded to 8-bit grayscale, RGB or RGBA, depending on their
+/// color type.
+#[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+pub struct TlgImageDecoder<R> {
+    reader: R,
+    info: TlgInfo,
+    limits: DecodeLimits,
+}
+
+impl<R: Read + Seek> TlgImageDecoder<R> {
+    /// Create a decoder, reading the headers and tags of the image.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let info = probe_tlg(&mut reader)?;
+        Ok(Self {
+            reader,
+            info,
+            limits: DecodeLimits::default(),
+        })
+    }
+
+    /// Information of the image, including its tags.
+    pub fn info(&self) -> &TlgInfo {
+        &self.info
+    }
+}
+
+impl<R: Read + Seek> ImageDecoder for TlgImageDecoder<R> {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.info.width, self.info.height)
+    }
+
+    fn color_type(&self) -> ColorType {
+        decode_format(self.info.color).1
+    }
+
+    fn read_image(mut self, buf: &mut [u8]) -> ImageResult<()> {
+        let pixel_format = decode_format(self.info.color).0;
+        let stride = self.info.width as usize * pixel_format.bytes_per_pixel(self.info.color);
+        let options = DecodeOptions {
+            pixel_format,
+            limits: self.limits,
+            ..Default::default()
+        };
+        load_tlg_into_with_options(&mut self.reader, buf, stride, &options)?;
+        Ok(())
+    }
+
+    fn read_image_boxed(self: Box<Self>, buf: &mut [u8]) -> ImageResult<()> {
+        (*self).read_image(buf)
+    }
+
+    fn set_limits(&mut self, limits: image::Limits) -> ImageResult<()> {
+        limits.check_support(&image::LimitSupport::default())?;
+        limits.check_dimensions(self.info.width, self.info.height)?;
+        self.limits.max_alloc_bytes = limits
+            .max_alloc
+            .map_or(usize::MAX, |n| usize::try_from(n).unwrap_or(usize::MAX));
+        Ok(())
+    }
+}
+
+/// Register TLG with the `image` crate, so [`image::ImageReader`] opens
+/// `.tlg` files and recognizes TLG data by its signature.
+///
+/// Returns `false` if a decoder was already registered for the `tlg`
+/// extension, in which case nothing is changed.
+#[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+pub fn register_image_format() -> bool {
+    let registered = image::hooks::register_decoding_hook(
+        "tlg".into(),
+        Box::new(|reader| Ok(Box::new(TlgImageDecoder::new(reader)?))),
+    );
+    if registered {
+        for signature in [
+            b"TLG0.0\x00sds\x1a",
+            b"TLG5.0\x00raw\x1a",
+            b"TLG6.0\x00raw\x1a",
+        ] {
+            image::hooks::register_format_detection_hook("tlg".into(), signature, None);
+        }
+    }
+    registered
+}
+
+/// [`ImageEncoder`] for TLG images.
+///
+/// Accepts 8-bit grayscale, RGB and RGBA data.
+#[cfg(feature = "encode")]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "image", feature = "encode"))))]
+pub struct TlgImageEncoder<W> {
+    writer: W,
+    version: u32,
+    options: EncodeOptions,
+}
+
+#[cfg(feature = "encode")]
+impl<W: Write> TlgImageEncoder<W> {
+    /// Create an encoder writing TLG5 images.
+    pub fn new(writer: W) -> Self {
+        Self::new_with_options(writer, 5, EncodeOptions::default())
+    }
+
+    /// Create an encoder writing images of the given TLG version with the
+    /// given options.
+    pub fn new_with_options(writer: W, version: u32, options: EncodeOptions) -> Self {
+        Self {
+            writer,
+            version,
+            options,
+        }
+    }
+}
+
+#[cfg(feature = "encode")]
+impl<W: Write> ImageEncoder for TlgImageEncoder<W> {
+    fn write_image(
+        self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ExtendedColorType,
+    ) -> ImageResult<()> {
+        let color = match color_type {
+            ExtendedColorType::L8 => TlgColorType::Grayscale8,
+            ExtendedColorType::Rgb8 => TlgColorType::Bgr24,
+            ExtendedColorType::Rgba8 => TlgColorType::Bgra32,
+            _ => {
+                return Err(ImageError::Unsupported(
+                    UnsupportedError::from_format_and_kind(
+                        format_hint(),
+                        UnsupportedErrorKind::Color(color_type),
+                    ),
+                ));
+            }
+        };
+        let mut data = buf.to_vec();
+        swap_red_blue(&mut data, color);
+        let tlg = Tlg {
+            tags: TlgTags::new(),
+            chunks: Vec::new(),
+            version: self.version,
+            width,
+            height,
+            color,
+            data,
+            tlg5_layout: None,
+        };
+        save_tlg_with_options(&tlg, self.writer, &self.options)?;
+        Ok(())
+    }
+}
+
+impl From<Tlg> for DynamicImage {
+    /// Convert an image whose data is in the native layout of its color type,
+    /// as returned by [`load_tlg`].
+    fn from(tlg: Tlg) -> Self {
+        let mut data = tlg.data;
+        let len = tlg.width as usize
+            * tlg.height as usize
+            * PixelFormat::Native.bytes_per_pixel(tlg.color);
+        data.resize(len, 0);
+        swap_red_blue(&mut data, tlg.color);
+        let (w, h) = (tlg.width, tlg.height);
+        match tlg.color {
+            TlgColorType::Grayscale8 => {
+                DynamicImage::ImageLuma8(image::ImageBuffer::from_raw(w, h, data).unwrap())
+            }
+            TlgColorType::Bgr24 => {
+                DynamicImage::ImageRgb8(image::ImageBuffer::from_raw(w, h, data).unwrap())
+            }
+            TlgColorType::Bgra32 => {
+                DynamicImage::ImageRgba8(image::ImageBuffer::from_raw(w, h, data).unwrap())
+            }
+        }
+    }
+}
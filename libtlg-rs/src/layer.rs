@@ -0,0 +1,243 @@
+//! Typed access to the KiriKiri layer tags of a TLG image.
+use crate::*;
+
+/// Layer compositing mode, as stored in the `mode` tag.
+///
+/// The names are the ones used by KAG, such as `alpha` or `psadd`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LayerMode {
+    /// `opaque`
+    Opaque,
+    /// `alpha`
+    Alpha,
+    /// `addalpha`
+    AddAlpha,
+    /// `add`
+    Add,
+    /// `sub`
+    Sub,
+    /// `mul`
+    Mul,
+    /// `dodge`
+    Dodge,
+    /// `darken`
+    Darken,
+    /// `lighten`
+    Lighten,
+    /// `screen`
+    Screen,
+    /// `psnormal`
+    PsNormal,
+    /// `psadd`
+    PsAdd,
+    /// `pssub`
+    PsSub,
+    /// `psmul`
+    PsMul,
+    /// `psscreen`
+    PsScreen,
+    /// `psoverlay`
+    PsOverlay,
+    /// `pshlight`
+    PsHardLight,
+    /// `psslight`
+    PsSoftLight,
+    /// `psdodge`
+    PsColorDodge,
+    /// `psdodge5`
+    PsColorDodge5,
+    /// `psburn`
+    PsColorBurn,
+    /// `pslighten`
+    PsLighten,
+    /// `psdarken`
+    PsDarken,
+    /// `psdiff`
+    PsDifference,
+    /// `psdiff5`
+    PsDifference5,
+    /// `psexcl`
+    PsExclusion,
+    /// Any other value, kept as is
+    Other(Vec<u8>),
+}
+
+const LAYER_MODES: [(&[u8], LayerMode); 26] = [
+    (b"opaque", LayerMode::Opaque),
+    (b"alpha", LayerMode::Alpha),
+    (b"addalpha", LayerMode::AddAlpha),
+    (b"add", LayerMode::Add),
+    (b"sub", LayerMode::Sub),
+    (b"mul", LayerMode::Mul),
+    (b"dodge", LayerMode::Dodge),
+    (b"darken", LayerMode::Darken),
+    (b"lighten", LayerMode::Lighten),
+    (b"screen", LayerMode::Screen),
+    (b"psnormal", LayerMode::PsNormal),
+    (b"psadd", LayerMode::PsAdd),
+    (b"pssub", LayerMode::PsSub),
+    (b"psmul", LayerMode::PsMul),
+    (b"psscreen", LayerMode::PsScreen),
+    (b"psoverlay", LayerMode::PsOverlay),
+    (b"pshlight", LayerMode::PsHardLight),
+    (b"psslight", LayerMode::PsSoftLight),
+    (b"psdodge", LayerMode::PsColorDodge),
+    (b"psdodge5", LayerMode::PsColorDodge5),
+    (b"psburn", LayerMode::PsColorBurn),
+    (b"pslighten", LayerMode::PsLighten),
+    (b"psdarken", LayerMode::PsDarken),
+    (b"psdiff", LayerMode::PsDifference),
+    (b"psdiff5", LayerMode::PsDifference5),
+    (b"psexcl", LayerMode::PsExclusion),
+];
+
+impl LayerMode {
+    /// Parse the value of a `mode` tag.
+    pub fn from_bytes(value: &[u8]) -> Self {
+        LAYER_MODES
+            .iter()
+            .find(|(name, _)| *name == value)
+            .map(|(_, mode)| mode.clone())
+            .unwrap_or_else(|| LayerMode::Other(value.to_vec()))
+    }
+
+    /// Value of the `mode` tag.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            LayerMode::Other(value) => value,
+            mode => LAYER_MODES
+                .iter()
+                .find(|(_, m)| m == mode)
+                .map(|(name, _)| *name)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl TlgTags {
+    /// Parse the value of a tag as a decimal integer.
+    ///
+    /// Returns `None` if the tag is missing.
+    pub fn get_int(&self, key: &[u8]) -> Result<Option<i32>> {
+        self.get(key)
+            .map(|v| {
+                core::str::from_utf8(v)
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .ok_or_else(|| TlgError::InvalidTagValue(key.to_vec()))
+            })
+            .transpose()
+    }
+
+    /// Set the value of a tag to a decimal integer.
+    pub fn set_int(&mut self, key: &[u8], value: i32) {
+        self.insert(key, value.to_string());
+    }
+
+    /// Horizontal offset of the layer, from the `offs_x` tag.
+    pub fn offs_x(&self) -> Result<Option<i32>> {
+        self.get_int(b"offs_x")
+    }
+
+    /// Set the `offs_x` tag.
+    pub fn set_offs_x(&mut self, value: i32) {
+        self.set_int(b"offs_x", value);
+    }
+
+    /// Vertical offset of the layer, from the `offs_y` tag.
+    pub fn offs_y(&self) -> Result<Option<i32>> {
+        self.get_int(b"offs_y")
+    }
+
+    /// Set the `offs_y` tag.
+    pub fn set_offs_y(&mut self, value: i32) {
+        self.set_int(b"offs_y", value);
+    }
+
+    /// Unit of the layer offsets, from the `offs_unit` tag, usually `pixel`.
+    pub fn offs_unit(&self) -> Option<&[u8]> {
+        self.get(b"offs_unit")
+    }
+
+    /// Set the `offs_unit` tag.
+    pub fn set_offs_unit(&mut self, value: impl Into<Vec<u8>>) {
+        self.insert(b"offs_unit", value);
+    }
+
+    /// Compositing mode of the layer, from the `mode` tag.
+    pub fn mode(&self) -> Option<LayerMode> {
+        self.get(b"mode").map(LayerMode::from_bytes)
+    }
+
+    /// Set the `mode` tag.
+    pub fn set_mode(&mut self, mode: &LayerMode) {
+        self.insert(b"mode", mode.as_bytes());
+    }
+
+    /// Opacity of the layer from 0 to 255, from the `opacity` tag.
+    pub fn opacity(&self) -> Result<Option<u8>> {
+        self.get_int(b"opacity")?
+            .map(|v| u8::try_from(v).map_err(|_| TlgError::InvalidTagValue(b"opacity".to_vec())))
+            .transpose()
+    }
+
+    /// Set the `opacity` tag.
+    pub fn set_opacity(&mut self, value: u8) {
+        self.set_int(b"opacity", value as i32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modes_round_trip() {
+        for (name, mode) in &LAYER_MODES {
+            assert_eq!(LayerMode::from_bytes(name), *mode);
+            assert_eq!(mode.as_bytes(), *name);
+        }
+        let other = LayerMode::from_bytes(b"psfoo");
+        assert_eq!(other, LayerMode::Other(b"psfoo".to_vec()));
+        assert_eq!(other.as_bytes(), b"psfoo");
+
+        let mut tags = TlgTags::new();
+        assert_eq!(tags.mode(), None);
+        tags.set_mode(&LayerMode::PsHardLight);
+        assert_eq!(tags.get(b"mode"), Some(&b"pshlight"[..]));
+        assert_eq!(tags.mode(), Some(LayerMode::PsHardLight));
+    }
+
+    #[test]
+    fn int_tags() {
+        let mut tags = TlgTags::new();
+        assert_eq!(tags.offs_x().unwrap(), None);
+        tags.set_offs_x(-12);
+        tags.set_offs_y(34);
+        tags.set_opacity(200);
+        assert_eq!(tags.get(b"offs_x"), Some(&b"-12"[..]));
+        assert_eq!(tags.offs_x().unwrap(), Some(-12));
+        assert_eq!(tags.offs_y().unwrap(), Some(34));
+        assert_eq!(tags.opacity().unwrap(), Some(200));
+
+        for (key, value) in [
+            (&b"offs_x"[..], &b"12px"[..]),
+            (b"offs_y", b""),
+            (b"offs_x", b"99999999999"),
+            (b"opacity", b"256"),
+            (b"opacity", b"-1"),
+        ] {
+            let mut tags = TlgTags::new();
+            tags.insert(key, value);
+            let result = match key {
+                b"opacity" => tags.opacity().map(|v| v.map(i32::from)),
+                _ => tags.get_int(key),
+            };
+            assert!(
+                matches!(result, Err(TlgError::InvalidTagValue(ref k)) if k == key),
+                "{}",
+                String::from_utf8_lossy(value)
+            );
+        }
+    }
+}
@@ -1,24 +1,99 @@
 //! A Rust library for processing TLG files.
+//!
+//! Without the default `std` feature, the crate is `no_std` and only needs
+//! `alloc`. Images are then decoded from memory with
+//! [`load_tlg_from_slice`], [`load_tlg_into_from_slice`] and
+//! [`probe_tlg_from_slice`].
+//!
+//! # Parallel decoding
+//!
+//! With the `parallel` feature, the functions decoding a whole image, such
+//! as [`load_tlg`] and [`load_tlg_into`], use the rayon thread pool when it
+//! has more than one thread. [`TlgDecoder`] still decodes one stripe at a
+//! time on the calling thread.
+//!
+//! The golomb values of TLG6 stripes are decoded in parallel while the rows
+//! of the preceding stripes are reconstructed. TLG5 channels are
+//! decompressed in parallel when they do not refer to the LZSS dictionary
+//! left by earlier blocks, and in file order otherwise. The TLG5 path reads
+//! the rest of the input at once, so it is only taken with
+//! [`PixelFormat::Native`] and when that input fits in
+//! [`DecodeLimits::max_alloc_bytes`]; other TLG5 images are decoded one block
+//! at a time.
 #![cfg_attr(docsrs, feature(doc_cfg))]
+#![cfg_attr(not(feature = "std"), no_std)]
+extern crate alloc;
+#[cfg(feature = "async")]
+mod async_tlg;
+#[cfg(feature = "std")]
+mod decoder;
+#[cfg(feature = "image")]
+mod image_tlg;
+mod layer;
 mod load_tlg;
+#[cfg(feature = "parallel")]
+mod parallel;
+mod pixel;
 #[cfg(feature = "encode")]
 mod save_tlg;
 #[cfg(feature = "encode")]
 mod slide;
 mod stream;
+mod tags;
 #[cfg(feature = "encode")]
 mod tlg5_saver;
+#[cfg(feature = "encode")]
+mod tlg6_saver;
 mod tvpgl;
 mod types;
+#[cfg(feature = "wasm-bindgen")]
+mod wasm;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
 use std::io::{Read, Seek};
 
-pub use types::{Tlg, TlgColorType, TlgError};
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+pub use async_tlg::{load_tlg_async, load_tlg_async_with_options};
+#[cfg(all(feature = "async", feature = "encode"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "async", feature = "encode"))))]
+pub use async_tlg::{save_tlg_async, save_tlg_async_with_options};
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+pub use decoder::TlgDecoder;
+#[cfg(all(feature = "image", feature = "encode"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "image", feature = "encode"))))]
+pub use image_tlg::TlgImageEncoder;
+#[cfg(feature = "image")]
+#[cfg_attr(docsrs, doc(cfg(feature = "image")))]
+pub use image_tlg::{TlgImageDecoder, register_image_format};
+pub use layer::LayerMode;
+pub use pixel::swap_red_blue;
+pub use tags::TlgTags;
+pub use types::{
+    DecodeLimits, DecodeOptions, PixelFormat, Tlg, Tlg5Layout, TlgChunk, TlgColorType, TlgError,
+    TlgInfo,
+};
+#[cfg(feature = "encode")]
+#[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
+pub use types::{EncodeOptions, LzssEffort, Tlg5EncodeOptions};
+#[cfg(feature = "wasm-bindgen")]
+#[cfg_attr(docsrs, doc(cfg(feature = "wasm-bindgen")))]
+pub use wasm::{WasmTlgImage, WasmTlgInfo, decode_wasm, probe_wasm};
 /// The result type for TLG operations.
-pub type Result<T> = std::result::Result<T, TlgError>;
-pub use load_tlg::load_tlg;
+pub type Result<T> = core::result::Result<T, TlgError>;
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+pub use load_tlg::{
+    load_tlg, load_tlg_into, load_tlg_into_with_options, load_tlg_with_options, probe_tlg,
+};
+pub use load_tlg::{load_tlg_from_slice, load_tlg_into_from_slice, probe_tlg_from_slice};
 #[cfg(feature = "encode")]
 #[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
-pub use save_tlg::save_tlg;
+pub use save_tlg::{save_tlg, save_tlg_with_options};
 
 /// Check if it's a valid TLG.
 ///
@@ -35,9 +110,11 @@ pub fn is_valid_tlg(data: &[u8]) -> bool {
 /// Check if it's a valid TLG.
 ///
 /// Same as [`is_valid_tlg`]
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
 pub fn check_tlg<T: Read + Seek>(mut data: T) -> Result<bool> {
     let mut header = [0; 11];
     data.rewind()?;
-    data.read_exact(&mut header)?;
+    stream::Source::read_fully(&mut data, &mut header)?;
     Ok(is_valid_tlg(&header))
 }
@@ -1,440 +1,883 @@
-use crate::stream::ReadExt;
+use crate::pixel::{write_native_row, write_word_row};
+use crate::stream::{ReadExt, SliceReader, Source};
 use crate::tvpgl::*;
 use crate::*;
-use overf::wrapping;
-use std::io::SeekFrom;
 
-fn load_tlg5<T: Read + Seek>(src: &mut T) -> Result<Tlg> {
+struct Tlg5Header {
+    colors: u8,
+    color: TlgColorType,
+    width: u32,
+    height: u32,
+    blockheight: u32,
+}
+
+struct Tlg6Header {
+    colors: u8,
+    color: TlgColorType,
+    width: u32,
+    height: u32,
+    max_bit_length: u32,
+}
+
+enum TlgHeader {
+    V5(Tlg5Header),
+    V6(Tlg6Header),
+}
+
+impl TlgHeader {
+    fn info(&self) -> TlgInfo {
+        let (version, width, height, color) = match self {
+            TlgHeader::V5(h) => (5, h.width, h.height, h.color),
+            TlgHeader::V6(h) => (6, h.width, h.height, h.color),
+        };
+        TlgInfo {
+            tags: Default::default(),
+            chunks: Vec::new(),
+            version,
+            width,
+            height,
+            color,
+        }
+    }
+}
+
+fn color_type_from_colors(colors: u8) -> Result<TlgColorType> {
+    match colors {
+        3 => Ok(TlgColorType::Bgr24),
+        4 => Ok(TlgColorType::Bgra32),
+        1 => Ok(TlgColorType::Grayscale8),
+        _ => Err(TlgError::UnsupportedColorType(colors)),
+    }
+}
+
+/// Check that a buffer of `bytes` bytes is within the allocation limit.
+pub fn check_alloc(limits: &DecodeLimits, bytes: usize) -> Result<()> {
+    if bytes > limits.max_alloc_bytes {
+        return Err(TlgError::LimitExceeded("max_alloc_bytes"));
+    }
+    Ok(())
+}
+
+/// Upper bound of the number of bytes a slide compressed stream expands to
+/// per input byte: a flag byte followed by 8 matches of 3 bytes produces
+/// 8 * 273 bytes out of 25.
+const SLIDE_MAX_EXPANSION: u64 = 88;
+
+/// Number of bytes left to read in `src`.
+pub fn remaining_len<T: Source>(src: &mut T) -> Result<u64> {
+    Ok(src.total_len()?.saturating_sub(src.position()?))
+}
+
+/// Check that the image is not empty and that its size is addressable.
+fn check_image_size(width: u32, height: u32) -> Result<()> {
+    if width == 0 || height == 0 {
+        return Err(TlgError::InvalidFormat);
+    }
+    // Stripe buffers hold at least 8 rows of 4 bytes per pixel.
+    (width as usize)
+        .checked_mul(height.max(TLG6_H_BLOCK_SIZE as u32) as usize)
+        .and_then(|n| n.checked_mul(4))
+        .filter(|&n| n <= isize::MAX as usize)
+        .ok_or_else(|| TlgError::Str("Image size is too large".to_string()))?;
+    Ok(())
+}
+
+fn read_tlg5_header<T: Source>(src: &mut T) -> Result<Tlg5Header> {
     let colors = src.read_u8()?;
     let width = src.read_u32()?;
     let height = src.read_u32()?;
     let blockheight = src.read_u32()?;
-    let color = match colors {
-        3 => TlgColorType::Bgr24,
-        4 => TlgColorType::Bgra32,
-        1 => TlgColorType::Grayscale8,
-        _ => return Err(TlgError::UnsupportedColorType(colors)),
-    };
-    let blockcount = ((height - 1) / blockheight) + 1;
-    src.seek_relative(blockcount as i64 * 4)?;
-    let stride = width as usize * colors as usize;
-    let mut output_data = vec![0u8; width as usize * height as usize * colors as usize];
-    let mut text = [0u8; 4096];
-    let mut inbuf = vec![0u8; blockheight as usize * width as usize + 10];
-    let mut outbuf = vec![vec![0u8; blockheight as usize * width as usize + 10]; colors as usize];
-    let mut prevline: Option<Vec<u8>> = None;
-    let mut r = 0;
-    for y_blk in (0..height).step_by(blockheight as usize) {
-        for c in 0..colors {
-            let mark = src.read_u8()?;
-            let size = src.read_u32()?;
-            if mark == 0 {
-                src.read_exact(&mut inbuf[..size as usize])?;
-                r = tlg5_decompress_slide(
-                    &mut outbuf[c as usize],
-                    &inbuf[..size as usize],
-                    size as usize,
-                    &mut text,
-                    r,
-                );
-            } else {
-                src.read_exact(&mut outbuf[c as usize][..size as usize])?;
-            }
-        }
-        let y_lim = (y_blk + blockheight).min(height);
-        let mut outbufp = Vec::new();
-        for c in 0..colors {
-            outbufp.push(outbuf[c as usize].as_slice());
-        }
-        for y in y_blk..y_lim {
-            let current = &mut output_data[(y as usize * stride)..(y as usize * stride + stride)];
-            match prevline.take() {
-                Some(prev) => match color {
-                    TlgColorType::Bgr24 => {
-                        tlg5_compose_colors3(current, &prev, &outbufp, width);
-                        outbufp[0] = &outbufp[0][width as usize..];
-                        outbufp[1] = &outbufp[1][width as usize..];
-                        outbufp[2] = &outbufp[2][width as usize..];
-                    }
-                    TlgColorType::Bgra32 => {
-                        tlg5_compose_colors4(current, &prev, &outbufp, width);
-                        outbufp[0] = &outbufp[0][width as usize..];
-                        outbufp[1] = &outbufp[1][width as usize..];
-                        outbufp[2] = &outbufp[2][width as usize..];
-                        outbufp[3] = &outbufp[3][width as usize..];
-                    }
-                    TlgColorType::Grayscale8 => {
-                        tlg5_compose_colors1(current, &prev, &outbufp, width);
-                        outbufp[0] = &outbufp[0][width as usize..];
-                    }
-                },
-                None => match color {
-                    TlgColorType::Bgra32 => {
-                        let mut current_pos = 0usize;
-                        let mut pr = 0u8;
-                        let mut pg = 0u8;
-                        let mut pb = 0u8;
-                        let mut pa = 0u8;
-                        for x in 0..width as usize {
-                            let mut b = outbufp[0][x];
-                            let g = outbufp[1][x];
-                            let mut r = outbufp[2][x];
-                            let a = outbufp[3][x];
-                            wrapping! {
-                                b += g;
-                                r += g;
-                                pb += b;
-                                pg += g;
-                                pr += r;
-                                pa += a;
-                            }
-                            current[current_pos] = pb;
-                            current_pos += 1;
-                            current[current_pos] = pg;
-                            current_pos += 1;
-                            current[current_pos] = pr;
-                            current_pos += 1;
-                            current[current_pos] = pa;
-                            current_pos += 1;
-                        }
-                        outbufp[0] = &outbufp[0][width as usize..];
-                        outbufp[1] = &outbufp[1][width as usize..];
-                        outbufp[2] = &outbufp[2][width as usize..];
-                        outbufp[3] = &outbufp[3][width as usize..];
-                    }
-                    TlgColorType::Bgr24 => {
-                        let mut current_pos = 0usize;
-                        let mut pr = 0u8;
-                        let mut pg = 0u8;
-                        let mut pb = 0u8;
-                        for x in 0..width as usize {
-                            let mut b = outbufp[0][x];
-                            let g = outbufp[1][x];
-                            let mut r = outbufp[2][x];
-                            wrapping! {
-                                b += g;
-                                r += g;
-                                pb += b;
-                                pg += g;
-                                pr += r;
-                            }
-                            current[current_pos] = pb;
-                            current_pos += 1;
-                            current[current_pos] = pg;
-                            current_pos += 1;
-                            current[current_pos] = pr;
-                            current_pos += 1;
-                        }
-                        outbufp[0] = &outbufp[0][width as usize..];
-                        outbufp[1] = &outbufp[1][width as usize..];
-                        outbufp[2] = &outbufp[2][width as usize..];
-                    }
-                    TlgColorType::Grayscale8 => {
-                        let mut current_pos = 0usize;
-                        let mut pb = 0u8;
-                        for x in 0..width as usize {
-                            let b = outbufp[0][x];
-                            wrapping! {
-                                pb += b;
-                            }
-                            current[current_pos] = pb;
-                            current_pos += 1;
-                        }
-                        outbufp[0] = &outbufp[0][width as usize..];
-                    }
-                },
-            }
-            prevline = Some(current.to_vec());
-        }
+    let color = color_type_from_colors(colors)?;
+    check_image_size(width, height)?;
+    if blockheight == 0 {
+        return Err(TlgError::InvalidFormat);
     }
-    Ok(Tlg {
-        tags: Default::default(),
-        version: 5,
+    Ok(Tlg5Header {
+        colors,
+        color,
         width,
         height,
-        color,
-        data: output_data,
+        blockheight,
     })
 }
 
-fn load_tlg6<T: Read + Seek>(src: &mut T) -> Result<Tlg> {
+fn read_tlg6_header<T: Source>(src: &mut T) -> Result<Tlg6Header> {
     let mut buf = [0u8; 4];
-    src.read_exact(&mut buf)?;
+    src.read_fully(&mut buf)?;
     let colors = buf[0];
-    let color_type = match colors {
-        3 => TlgColorType::Bgr24,
-        4 => TlgColorType::Bgra32,
-        1 => TlgColorType::Grayscale8,
-        _ => return Err(TlgError::UnsupportedColorType(colors)),
-    };
+    let color = color_type_from_colors(colors)?;
     if buf[1] != 0 {
-        return Err(TlgError::Str("Data flags must be 0".to_string()));
+        return Err(TlgError::UnsupportedDataFlags(buf[1]));
     }
     if buf[2] != 0 {
-        return Err(TlgError::Str("Color types must be 0".to_string()));
+        return Err(TlgError::UnsupportedColorTypes(buf[2]));
     }
     if buf[3] != 0 {
-        return Err(TlgError::Str(
-            "External golomb bit length table is not yet supported.".to_string(),
-        ));
+        return Err(TlgError::UnsupportedGolombTable);
     }
     let width = src.read_u32()?;
     let height = src.read_u32()?;
+    check_image_size(width, height)?;
     let max_bit_length = src.read_u32()?;
-    let x_block_count = (width - 1) / (TLG6_W_BLOCK_SIZE as u32) + 1;
-    let y_block_count = (height - 1) / (TLG6_H_BLOCK_SIZE as u32) + 1;
-    let main_count = width / (TLG6_W_BLOCK_SIZE as u32);
-    let fraction = width - main_count * TLG6_W_BLOCK_SIZE as u32;
-    let mut bit_pool = vec![0u8; max_bit_length as usize / 8 + 5];
-    let mut pixelbuf = vec![0u32; width as usize * TLG6_H_BLOCK_SIZE + 1];
-    let mut filter_types = vec![0u8; x_block_count as usize * y_block_count as usize];
-    let mut lzss_text = [0u8; 4096];
-    let zero = if colors == 3 { 0xff_00_00_00u32 } else { 0 };
-    let zeroline = vec![zero; width as usize];
-    {
-        let mut p = 0;
-        let mut i = 0;
-        while i < 0x20u8 {
-            let mut j = 0;
-            while j < 0x10u8 {
-                lzss_text[p] = i;
-                p += 1;
-                lzss_text[p] = i;
-                p += 1;
-                lzss_text[p] = i;
-                p += 1;
-                lzss_text[p] = i;
-                p += 1;
-                lzss_text[p] = j;
-                p += 1;
-                lzss_text[p] = j;
-                p += 1;
-                lzss_text[p] = j;
-                p += 1;
-                lzss_text[p] = j;
-                p += 1;
-                j += 1;
-            }
-            i += 1;
+    Ok(Tlg6Header {
+        colors,
+        color,
+        width,
+        height,
+        max_bit_length,
+    })
+}
+
+/// Read the mark and header of a raw (non-SDS) TLG5/TLG6 stream.
+fn read_raw_header<T: Source>(src: &mut T) -> Result<TlgHeader> {
+    let mut mark = [0; 11];
+    src.read_fully(&mut mark)?;
+    if &mark == b"TLG5.0\x00raw\x1a" {
+        Ok(TlgHeader::V5(read_tlg5_header(src)?))
+    } else if &mark == b"TLG6.0\x00raw\x1a" {
+        Ok(TlgHeader::V6(read_tlg6_header(src)?))
+    } else {
+        Err(TlgError::InvalidFormat)
+    }
+}
+
+/// Decoder state of a TLG5 stream, producing one block of rows at a time.
+pub struct Tlg5StripeDecoder {
+    pub colors: u8,
+    pub color: TlgColorType,
+    pub width: u32,
+    pub height: u32,
+    pub blockheight: u32,
+    /// First row of the next block
+    pub y: u32,
+    pub text: Vec<u8>,
+    pub r: usize,
+    pub pixel_format: PixelFormat,
+    #[cfg_attr(not(feature = "parallel"), allow(dead_code))]
+    pub limits: DecodeLimits,
+    /// Layout of the blocks read so far, if it is recorded
+    pub layout: Option<Tlg5Layout>,
+    inbuf: Vec<u8>,
+    outbuf: Vec<Vec<u8>>,
+    prevline: Vec<u8>,
+    curline: Vec<u8>,
+}
+
+impl Tlg5StripeDecoder {
+    fn new<T: Source>(src: &mut T, header: Tlg5Header, options: &DecodeOptions) -> Result<Self> {
+        let Tlg5Header {
+            colors,
+            color,
+            width,
+            height,
+            blockheight,
+        } = header;
+        let layout = options.keep_tlg5_layout.then(|| Tlg5Layout {
+            block_height: blockheight,
+            raw: Vec::new(),
+        });
+        // A block never has more rows than the image.
+        let blockheight = blockheight.min(height);
+        let blockcount = ((height - 1) / blockheight) + 1;
+        let limits = &options.limits;
+        // The block size table is skipped, as the channel headers give the
+        // size of every channel.
+        for _ in 0..blockcount {
+            src.read_u32()?;
         }
+        // Each channel of each block has a 5 bytes header and expands to at
+        // most SLIDE_MAX_EXPANSION times its size.
+        let headers = blockcount as u64 * colors as u64 * 5;
+        let planes = width as u64 * height as u64 * colors as u64;
+        let available = remaining_len(src)?;
+        if available < headers || planes > (available - headers).saturating_mul(SLIDE_MAX_EXPANSION)
+        {
+            return Err(TlgError::UnexpectedEof);
+        }
+        let blocksize = blockheight as usize * width as usize + 10;
+        check_alloc(limits, blocksize)?;
+        check_alloc(limits, blocksize * colors as usize)?;
+        let line_size = width as usize * colors as usize;
+        check_alloc(limits, line_size)?;
+        let mut outbuf = Vec::with_capacity(colors as usize);
+        for _ in 0..colors {
+            outbuf.push(alloc_filled(blocksize, 0u8)?);
+        }
+        Ok(Self {
+            colors,
+            color,
+            width,
+            height,
+            blockheight,
+            y: 0,
+            text: vec![0u8; 4096],
+            r: 0,
+            pixel_format: options.pixel_format,
+            limits: options.limits,
+            layout,
+            inbuf: alloc_filled(blocksize, 0u8)?,
+            outbuf,
+            prevline: alloc_filled(line_size, 0u8)?,
+            curline: alloc_filled(line_size, 0u8)?,
+        })
     }
-    {
-        let inbuf_size = src.read_u32()? as usize;
-        let mut inbuf = vec![0u8; inbuf_size];
-        src.read_exact(&mut inbuf)?;
-        tlg5_decompress_slide(&mut filter_types, &inbuf, inbuf_size, &mut lzss_text, 0);
-    }
-    let mut prevline = zeroline;
-    let mut outbuf = vec![0u32; width as usize * height as usize];
-    for y in (0..height).step_by(TLG6_H_BLOCK_SIZE) {
-        let y_lim = (y + TLG6_H_BLOCK_SIZE as u32).min(height);
-        let pixel_count = (y_lim - y) as usize * width as usize;
-        for c in 0..colors {
-            let mut bit_length = src.read_u32()?;
-            let method = (bit_length >> 30) & 3;
-            bit_length &= 0x3fff_ffff;
-            let byte_length = (bit_length + 7) / 8;
-            if byte_length as usize >= bit_pool.len() {
-                return Err(TlgError::Str(
-                    "Bit pool is too small for the given bit length".to_string(),
-                ));
+
+    /// Maximum number of rows returned by [`Self::decode_stripe`].
+    pub fn stripe_height(&self) -> u32 {
+        self.blockheight
+    }
+
+    /// Decode the next block into `out`, whose rows are `stride` bytes apart,
+    /// returning the number of rows written.
+    pub fn decode_stripe<T: Source>(
+        &mut self,
+        src: &mut T,
+        out: &mut [u8],
+        stride: usize,
+    ) -> Result<u32> {
+        let width = self.width;
+        let row_size = width as usize * self.pixel_format.bytes_per_pixel(self.color);
+        let y_blk = self.y;
+        if y_blk >= self.height {
+            return Ok(0);
+        }
+        for c in 0..self.colors as usize {
+            let mark = src.read_u8()?;
+            let size = src.read_u32()? as usize;
+            if let Some(layout) = &mut self.layout {
+                layout.raw.push(mark != 0);
+            }
+            if mark == 0 {
+                let inbuf = self
+                    .inbuf
+                    .get_mut(..size)
+                    .ok_or(TlgError::IndexOutOfRange)?;
+                src.read_fully(inbuf)?;
+                self.r = tlg5_decompress_slide(
+                    &mut self.outbuf[c],
+                    inbuf,
+                    size,
+                    &mut self.text,
+                    self.r,
+                )?;
+            } else {
+                let outbuf = self.outbuf[c]
+                    .get_mut(..size)
+                    .ok_or(TlgError::IndexOutOfRange)?;
+                src.read_fully(outbuf)?;
             }
-            src.read_exact(&mut bit_pool[..byte_length as usize])?;
-            match method {
-                0 => {
-                    tlg6_decode_golomb_values(
-                        &mut pixelbuf,
-                        pixel_count,
-                        &bit_pool,
-                        c == 0 && colors != 1,
-                        c,
-                    )?;
+        }
+        let y_lim = y_blk.saturating_add(self.blockheight).min(self.height);
+        let mut outbufp = Vec::new();
+        for c in 0..self.colors {
+            outbufp.push(self.outbuf[c as usize].as_slice());
+        }
+        for row in out.chunks_mut(stride).take((y_lim - y_blk) as usize) {
+            let current = &mut self.curline;
+            // The line above the first row is treated as all zero.
+            match self.color {
+                TlgColorType::Bgr24 => {
+                    tlg5_compose_colors3(current, &self.prevline, &outbufp, width);
+                }
+                TlgColorType::Bgra32 => {
+                    tlg5_compose_colors4(current, &self.prevline, &outbufp, width);
+                }
+                TlgColorType::Grayscale8 => {
+                    tlg5_compose_colors1(current, &self.prevline, &outbufp, width);
                 }
-                _ => return Err(TlgError::UnsupportedCompressedMethod(method as u8)),
             }
+            for p in outbufp.iter_mut() {
+                *p = &p[width as usize..];
+            }
+            write_native_row(self.pixel_format, self.color, &mut row[..row_size], current);
+            core::mem::swap(&mut self.prevline, &mut self.curline);
         }
-        let ft = &filter_types[(y as usize / TLG6_H_BLOCK_SIZE) * x_block_count as usize..];
+        self.y = y_lim;
+        Ok(y_lim - y_blk)
+    }
+}
+
+/// Decoder state of a TLG6 stream, producing one 8-row stripe at a time.
+pub struct Tlg6StripeDecoder {
+    pub colors: u8,
+    pub color: TlgColorType,
+    pub width: u32,
+    pub height: u32,
+    /// First row of the next stripe
+    pub y: u32,
+    pub pixel_format: PixelFormat,
+    x_block_count: u32,
+    main_count: u32,
+    fraction: u32,
+    /// Largest size in bytes of a golomb bit stream
+    max_byte_length: usize,
+    bit_pool: Vec<u8>,
+    pixelbuf: Vec<u32>,
+    filter_types: Vec<u8>,
+    zero: u32,
+    prevline: Vec<u32>,
+    curline: Vec<u32>,
+}
+
+impl Tlg6StripeDecoder {
+    fn new<T: Source>(src: &mut T, header: Tlg6Header, options: &DecodeOptions) -> Result<Self> {
+        let Tlg6Header {
+            colors,
+            color,
+            width,
+            height,
+            max_bit_length,
+        } = header;
+        let x_block_count = (width - 1) / (TLG6_W_BLOCK_SIZE as u32) + 1;
+        let y_block_count = (height - 1) / (TLG6_H_BLOCK_SIZE as u32) + 1;
+        let main_count = width / (TLG6_W_BLOCK_SIZE as u32);
+        let fraction = width - main_count * TLG6_W_BLOCK_SIZE as u32;
+        let filter_count = (x_block_count as usize)
+            .checked_mul(y_block_count as usize)
+            .ok_or(TlgError::IndexOutOfRange)?;
+        let limits = &options.limits;
+        check_alloc(limits, filter_count)?;
+        // The golomb values of a stripe, which is only as tall as the image.
+        let pixelbuf_len = width as usize * height.min(TLG6_H_BLOCK_SIZE as u32) as usize + 1;
+        check_alloc(limits, pixelbuf_len * 4)?;
+        check_alloc(limits, width as usize * 4)?;
+        let max_byte_length = max_bit_length as usize / 8 + 4;
+        // The bit pool holds a bit stream and 4 bytes of padding.
+        check_alloc(limits, max_byte_length + 4)?;
+        let zero = if colors == 3 { 0xff_00_00_00u32 } else { 0 };
+        let inbuf_size = src.read_u32()? as usize;
+        check_alloc(limits, inbuf_size)?;
+        let mut inbuf = Vec::new();
+        src.read_exact_vec(&mut inbuf, inbuf_size)?;
+        // Nothing is allocated for the image until the compressed filter
+        // types and the bit length of every stripe are known to fit.
+        let stripe_headers = y_block_count as u64 * colors as u64 * 4;
+        if filter_count as u64 > (inbuf_size as u64).saturating_mul(SLIDE_MAX_EXPANSION)
+            || remaining_len(src)? < stripe_headers
+        {
+            return Err(TlgError::UnexpectedEof);
+        }
+        let mut filter_types = alloc_filled(filter_count, 0u8)?;
+        let mut lzss_text = tlg6_init_lzss_text();
+        tlg5_decompress_slide(&mut filter_types, &inbuf, inbuf_size, &mut lzss_text, 0)?;
+        Ok(Self {
+            colors,
+            color,
+            width,
+            height,
+            y: 0,
+            pixel_format: options.pixel_format,
+            x_block_count,
+            main_count,
+            fraction,
+            max_byte_length,
+            bit_pool: Vec::new(),
+            pixelbuf: alloc_filled(pixelbuf_len, 0u32)?,
+            filter_types,
+            zero,
+            prevline: alloc_filled(width as usize, zero)?,
+            curline: alloc_filled(width as usize, 0u32)?,
+        })
+    }
+
+    /// Maximum number of rows returned by [`Self::decode_stripe`].
+    pub fn stripe_height(&self) -> u32 {
+        TLG6_H_BLOCK_SIZE as u32
+    }
+
+    /// Decode the next stripe into `out`, whose rows are `stride` bytes apart,
+    /// returning the number of rows written.
+    pub fn decode_stripe<T: Source>(
+        &mut self,
+        src: &mut T,
+        out: &mut [u8],
+        stride: usize,
+    ) -> Result<u32> {
+        if self.y >= self.height {
+            return Ok(0);
+        }
+        let mut pixelbuf = core::mem::take(&mut self.pixelbuf);
+        let result = self.read_stripe_values(src, &mut pixelbuf);
+        let result = result.and_then(|_| self.compose_stripe(&pixelbuf, out, stride));
+        self.pixelbuf = pixelbuf;
+        result
+    }
+
+    fn read_stripe_values<T: Source>(&mut self, src: &mut T, pixelbuf: &mut [u32]) -> Result<()> {
+        let pixel_count = self.stripe_pixel_count(self.y);
+        let mut bit_pool = core::mem::take(&mut self.bit_pool);
+        for c in 0..self.colors {
+            self.read_bit_stream(src, &mut bit_pool)?;
+            tlg6_decode_golomb_values(
+                pixelbuf,
+                pixel_count,
+                &bit_pool,
+                c == 0 && self.colors != 1,
+                c,
+            )?;
+        }
+        self.bit_pool = bit_pool;
+        Ok(())
+    }
+
+    /// Number of pixels in the stripe starting at row `y`.
+    pub fn stripe_pixel_count(&self, y: u32) -> usize {
+        let y_lim = (y + TLG6_H_BLOCK_SIZE as u32).min(self.height);
+        (y_lim - y) as usize * self.width as usize
+    }
+
+    /// Read the golomb bit stream of a channel into `bit_pool`, followed by
+    /// the 4 bytes of zero padding the golomb decoder may read past its end.
+    pub fn read_bit_stream<T: Source>(&self, src: &mut T, bit_pool: &mut Vec<u8>) -> Result<()> {
+        let bit_length = src.read_u32()?;
+        let method = (bit_length >> 30) & 3;
+        if method != 0 {
+            return Err(TlgError::UnsupportedCompressedMethod(method as u8));
+        }
+        let byte_length = (bit_length & 0x3fff_ffff).div_ceil(8) as usize;
+        if byte_length > self.max_byte_length {
+            return Err(TlgError::Str(
+                "Bit pool is too small for the given bit length".to_string(),
+            ));
+        }
+        src.read_exact_vec(bit_pool, byte_length)?;
+        bit_pool.resize(byte_length + 4, 0);
+        Ok(())
+    }
+
+    /// Reconstruct the rows of the next stripe from its golomb decoded
+    /// values, returning the number of rows written.
+    pub fn compose_stripe(
+        &mut self,
+        pixelbuf: &[u32],
+        out: &mut [u8],
+        stride: usize,
+    ) -> Result<u32> {
+        let width = self.width;
+        let y = self.y;
+        let y_lim = (y + TLG6_H_BLOCK_SIZE as u32).min(self.height);
+        let ft =
+            &self.filter_types[(y as usize / TLG6_H_BLOCK_SIZE) * self.x_block_count as usize..];
         let skip_bytes = (y_lim - y) as usize * TLG6_W_BLOCK_SIZE;
-        for yy in y..y_lim {
-            let curline =
-                &mut outbuf[(yy as usize * width as usize)..(yy as usize + 1) * width as usize];
+        let row_size = width as usize * self.pixel_format.bytes_per_pixel(self.color);
+        for (yy, row) in (y..y_lim).zip(out.chunks_mut(stride)) {
+            let row = &mut row[..row_size];
             let dir = (yy & 1) ^ 1 != 0;
             let oddskip = ((y_lim - yy - 1) as isize) - (yy - y) as isize;
-            if main_count != 0 {
+            if self.main_count != 0 {
                 let start = TLG6_W_BLOCK_SIZE.min(width as usize) * (yy - y) as usize;
                 tlg6_decode_line(
-                    &prevline,
-                    curline,
+                    &self.prevline,
+                    &mut self.curline,
                     width,
                     0,
-                    main_count as usize,
+                    self.main_count as usize,
                     ft,
                     skip_bytes,
-                    &pixelbuf,
+                    pixelbuf,
                     start,
-                    zero,
+                    self.zero,
                     oddskip,
                     dir,
                 )?;
             }
-            if main_count != x_block_count {
-                let ww = TLG6_W_BLOCK_SIZE.min(fraction as usize);
+            if self.main_count != self.x_block_count {
+                let ww = TLG6_W_BLOCK_SIZE.min(self.fraction as usize);
                 let start = ww * (yy - y) as usize;
                 tlg6_decode_line(
-                    &prevline,
-                    curline,
+                    &self.prevline,
+                    &mut self.curline,
                     width,
-                    main_count as usize,
-                    x_block_count as usize,
+                    self.main_count as usize,
+                    self.x_block_count as usize,
                     ft,
                     skip_bytes,
-                    &pixelbuf,
+                    pixelbuf,
                     start,
-                    zero,
+                    self.zero,
                     oddskip,
                     dir,
                 )?;
             }
-            prevline = curline.to_vec();
+            write_word_row(self.pixel_format, self.color, row, &self.curline);
+            core::mem::swap(&mut self.prevline, &mut self.curline);
         }
+        self.y = y_lim;
+        Ok(y_lim - y)
     }
-    let mut data = Vec::with_capacity(outbuf.len() * colors as usize);
-    for p in outbuf {
-        let t = p.to_le_bytes();
-        let b = t[0];
-        let g = t[1];
-        let r = t[2];
-        let a = t[3];
-        match color_type {
-            TlgColorType::Bgr24 => {
-                data.extend_from_slice(&[b, g, r]);
+}
+
+/// Stripe decoder of either TLG version.
+pub enum StripeDecoder {
+    V5(Tlg5StripeDecoder),
+    V6(Tlg6StripeDecoder),
+}
+
+impl StripeDecoder {
+    /// Read the pixel data preamble following the header.
+    fn new<T: Source>(src: &mut T, header: TlgHeader, options: &DecodeOptions) -> Result<Self> {
+        Ok(match header {
+            TlgHeader::V5(header) => {
+                StripeDecoder::V5(Tlg5StripeDecoder::new(src, header, options)?)
             }
-            TlgColorType::Bgra32 => {
-                data.extend_from_slice(&[b, g, r, a]);
+            TlgHeader::V6(header) => {
+                StripeDecoder::V6(Tlg6StripeDecoder::new(src, header, options)?)
             }
-            TlgColorType::Grayscale8 => {
-                data.extend_from_slice(&[b]);
+        })
+    }
+
+    pub fn stripe_height(&self) -> u32 {
+        match self {
+            StripeDecoder::V5(d) => d.stripe_height(),
+            StripeDecoder::V6(d) => d.stripe_height(),
+        }
+    }
+
+    pub fn decode_stripe<T: Source>(
+        &mut self,
+        src: &mut T,
+        out: &mut [u8],
+        stride: usize,
+    ) -> Result<u32> {
+        match self {
+            StripeDecoder::V5(d) => d.decode_stripe(src, out, stride),
+            StripeDecoder::V6(d) => d.decode_stripe(src, out, stride),
+        }
+    }
+
+    /// Take the recorded layout of a TLG5 stream.
+    pub fn take_tlg5_layout(&mut self) -> Option<Tlg5Layout> {
+        match self {
+            StripeDecoder::V5(d) => d.layout.take(),
+            StripeDecoder::V6(_) => None,
+        }
+    }
+
+    /// Decode all remaining stripes into `out`, whose rows are `stride` bytes
+    /// apart.
+    pub fn decode_all<T: Source>(
+        &mut self,
+        src: &mut T,
+        out: &mut [u8],
+        stride: usize,
+    ) -> Result<()> {
+        #[cfg(feature = "parallel")]
+        if rayon::current_num_threads() > 1 {
+            match self {
+                StripeDecoder::V5(d) if d.pixel_format == PixelFormat::Native => {
+                    // The rest of the input is read at once.
+                    let len = remaining_len(src)?;
+                    if usize::try_from(len).is_ok_and(|n| n <= d.limits.max_alloc_bytes) {
+                        return crate::parallel::decode_tlg5(d, src, len as usize, out, stride);
+                    }
+                }
+                StripeDecoder::V6(d) => return crate::parallel::decode_tlg6(d, src, out, stride),
+                _ => {}
+            }
+        }
+        let stripe_size = stride * self.stripe_height() as usize;
+        for stripe in out.chunks_mut(stripe_size) {
+            if self.decode_stripe(src, stripe, stride)? == 0 {
+                break;
             }
+        }
+        Ok(())
+    }
+}
+
+/// Read the headers of a TLG file, including the tags of a TLG0.0 SDS file.
+///
+/// If `alloc_image` is set, the size of the whole decoded image is checked
+/// against the allocation limit before any stripe buffer is allocated.
+///
+/// On return, `src` is positioned at the start of the pixel data and the
+/// stripe decoder is ready to decode the first stripe.
+pub fn open_tlg<T: Source>(
+    src: &mut T,
+    options: &DecodeOptions,
+    alloc_image: bool,
+) -> Result<(TlgInfo, StripeDecoder)> {
+    src.seek_to(0)?;
+    let mut mark = [0; 11];
+    src.read_fully(&mut mark)?;
+    let limits = &options.limits;
+    let (header, info) = if &mark == b"TLG0.0\x00sds\x1a" {
+        let rawlen = src.read_u32()?;
+        let header = read_raw_header(src)?;
+        let mut info = header.info();
+        check_pixels(&info, limits)?;
+        let data_pos = src.position()?;
+        read_sds_chunks(src, rawlen, &mut info, limits)?;
+        src.seek_to(data_pos)?;
+        (header, info)
+    } else {
+        src.seek_to(0)?;
+        let header = read_raw_header(src)?;
+        let info = header.info();
+        check_pixels(&info, limits)?;
+        (header, info)
+    };
+    if alloc_image {
+        check_alloc(limits, image_size(&info, options)?)?;
+    }
+    Ok((info, StripeDecoder::new(src, header, options)?))
+}
+
+/// Size in bytes of the decoded image, with rows packed.
+fn image_size(info: &TlgInfo, options: &DecodeOptions) -> Result<usize> {
+    (info.width as usize * options.pixel_format.bytes_per_pixel(info.color))
+        .checked_mul(info.height as usize)
+        .ok_or(TlgError::LimitExceeded("max_alloc_bytes"))
+}
+
+fn check_pixels(info: &TlgInfo, limits: &DecodeLimits) -> Result<()> {
+    if info.width as u64 * info.height as u64 > limits.max_pixels {
+        return Err(TlgError::LimitExceeded("max_pixels"));
+    }
+    Ok(())
+}
+
+/// Parse the content of a `tags` chunk.
+///
+/// Returns `false` if the chunk is malformed and no more chunks should be read.
+fn parse_tags(tag: &[u8], tags: &mut TlgTags) -> bool {
+    let mut rest = tag;
+    while !rest.is_empty() {
+        let Some((name, r)) = parse_tag_field(rest) else {
+            return false;
+        };
+        let Some(r) = r.strip_prefix(b"=") else {
+            return false;
         };
+        let Some((value, r)) = parse_tag_field(r) else {
+            return false;
+        };
+        let Some(r) = r.strip_prefix(b",") else {
+            return false;
+        };
+        tags.append(name, value);
+        rest = r;
+    }
+    true
+}
+
+/// Parse a `length:bytes` field of a `tags` chunk, returning the bytes and
+/// the remaining input.
+fn parse_tag_field(s: &[u8]) -> Option<(&[u8], &[u8])> {
+    let digits = s.iter().take_while(|c| c.is_ascii_digit()).count();
+    let mut len = 0usize;
+    for &c in &s[..digits] {
+        len = len.checked_mul(10)?.checked_add((c - b'0') as usize)?;
+    }
+    let s = s[digits..].strip_prefix(b":")?;
+    if s.len() < len {
+        return None;
     }
+    Some(s.split_at(len))
+}
+
+/// Read the chunks following the raw image data of a TLG0.0 SDS file into
+/// the tags and chunks of `info`.
+fn read_sds_chunks<T: Source>(
+    src: &mut T,
+    rawlen: u32,
+    info: &mut TlgInfo,
+    limits: &DecodeLimits,
+) -> Result<()> {
+    let newlen = rawlen as u64 + 15;
+    src.seek_to(newlen)?;
+    let mut chunks = 0usize;
+    let mut tag_bytes = 0usize;
+    loop {
+        let mut chunkname = [0; 4];
+        if src.read_some(&mut chunkname)? != 4 {
+            break;
+        }
+        chunks += 1;
+        if chunks > limits.max_chunks {
+            return Err(TlgError::LimitExceeded("max_chunks"));
+        }
+        let chunksize = src.read_u32()?;
+        if &chunkname == b"tags" {
+            tag_bytes = tag_bytes.saturating_add(chunksize as usize);
+            if tag_bytes > limits.max_tag_bytes {
+                return Err(TlgError::LimitExceeded("max_tag_bytes"));
+            }
+            let mut tag = Vec::new();
+            src.read_exact_vec(&mut tag, chunksize as usize)?;
+            let count = info.tags.len();
+            let valid = parse_tags(&tag, &mut info.tags);
+            let count = (info.tags.len() - count) as u32;
+            info.chunks.push(TlgChunk {
+                name: chunkname,
+                data: count.to_le_bytes().to_vec(),
+            });
+            if !valid {
+                break;
+            }
+        } else {
+            check_alloc(limits, chunksize as usize)?;
+            let mut data = Vec::new();
+            src.read_exact_vec(&mut data, chunksize as usize)?;
+            info.chunks.push(TlgChunk {
+                name: chunkname,
+                data,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn decode<T: Source>(src: &mut T, options: &DecodeOptions) -> Result<Tlg> {
+    let (info, mut decoder) = open_tlg(src, options, true)?;
+    let row_size = info.width as usize * options.pixel_format.bytes_per_pixel(info.color);
+    let len = image_size(&info, options)?;
+    // Pages of a zeroed allocation are only mapped when first written.
+    let mut data = vec![0u8; len];
+    decoder.decode_all(src, &mut data, row_size)?;
     Ok(Tlg {
-        tags: Default::default(),
-        version: 6,
-        width,
-        height,
-        color: color_type,
+        tags: info.tags,
+        chunks: info.chunks,
+        version: info.version,
+        width: info.width,
+        height: info.height,
+        color: info.color,
         data,
+        tlg5_layout: decoder.take_tlg5_layout(),
     })
 }
 
-fn internal_load_tlg<T: Read + Seek>(src: &mut T) -> Result<Tlg> {
-    let mut mark = [0; 11];
-    src.read_exact(&mut mark)?;
-    if &mark == b"TLG5.0\x00raw\x1a" {
-        load_tlg5(src)
-    } else if &mark == b"TLG6.0\x00raw\x1a" {
-        load_tlg6(src)
-    } else {
-        Err(TlgError::InvalidFormat)
+fn decode_into<T: Source>(
+    src: &mut T,
+    out: &mut [u8],
+    stride: usize,
+    options: &DecodeOptions,
+) -> Result<TlgInfo> {
+    let (info, mut decoder) = open_tlg(src, options, false)?;
+    let row_size = info.width as usize * options.pixel_format.bytes_per_pixel(info.color);
+    if stride < row_size {
+        return Err(TlgError::Str(format!(
+            "Stride {stride} is smaller than the row size {row_size}"
+        )));
+    }
+    let needed = (info.height as usize - 1)
+        .checked_mul(stride)
+        .and_then(|n| n.checked_add(row_size))
+        .ok_or(TlgError::IndexOutOfRange)?;
+    if out.len() < needed {
+        return Err(TlgError::Str(format!(
+            "Buffer of {} bytes is too small, {needed} bytes are needed",
+            out.len()
+        )));
     }
+    decoder.decode_all(src, out, stride)?;
+    Ok(info)
 }
 
-/// Decode TLG image
-pub fn load_tlg<T: Read + Seek>(mut src: T) -> Result<Tlg> {
-    src.rewind()?;
+fn probe<T: Source>(src: &mut T) -> Result<TlgInfo> {
+    src.seek_to(0)?;
     let mut mark = [0; 11];
-    src.read_exact(&mut mark)?;
+    src.read_fully(&mut mark)?;
     if &mark == b"TLG0.0\x00sds\x1a" {
         let rawlen = src.read_u32()?;
-        let mut tlg = internal_load_tlg(&mut src)?;
-        let newlen = rawlen as u64 + 15;
-        src.seek(SeekFrom::Start(newlen))?;
-        let mut check = true;
-        while check {
-            let mut chunkname = [0; 4];
-            if src.read(&mut chunkname)? != 4 {
-                break;
-            }
-            let chunksize = src.read_u32()?;
-            if &chunkname == b"tags" {
-                let mut tag = vec![0; chunksize as usize];
-                src.read_exact(&mut tag)?;
-                let mut i = 0;
-                let len = tag.len();
-                while i < len {
-                    let mut namelen = 0usize;
-                    let mut c = tag[i];
-                    let mut ok = true;
-                    while c >= b'0' && c <= b'9' {
-                        namelen = namelen * 10 + (c - b'0') as usize;
-                        i += 1;
-                        if i >= len {
-                            ok = false;
-                            break;
-                        }
-                        c = tag[i];
-                    }
-                    if !ok {
-                        break;
-                    }
-                    if c != b':' {
-                        check = false;
-                        break;
-                    }
-                    i += 1;
-                    let name = tag[i..i + namelen].to_vec();
-                    i += namelen;
-                    if i >= len {
-                        break;
-                    }
-                    c = tag[i];
-                    if c != b'=' {
-                        check = false;
-                        break;
-                    }
-                    i += 1;
-                    let mut valuelen = 0usize;
-                    c = tag[i];
-                    ok = true;
-                    while c >= b'0' && c <= b'9' {
-                        valuelen = valuelen * 10 + (c - b'0') as usize;
-                        i += 1;
-                        if i >= len {
-                            ok = false;
-                            break;
-                        }
-                        c = tag[i];
-                    }
-                    if !ok {
-                        break;
-                    }
-                    if c != b':' {
-                        check = false;
-                        break;
-                    }
-                    i += 1;
-                    let value = tag[i..i + valuelen].to_vec();
-                    i += valuelen;
-                    if i >= len {
-                        check = false;
-                        break;
-                    }
-                    c = tag[i];
-                    if c != b',' {
-                        check = false;
-                        break;
-                    }
-                    i += 1;
-                    tlg.tags.insert(name, value);
-                }
-            } else {
-                // skip the chunk
-                src.seek_relative(chunksize as i64)?;
-            }
-        }
-        Ok(tlg)
+        let mut info = read_raw_header(src)?.info();
+        read_sds_chunks(src, rawlen, &mut info, &DecodeLimits::default())?;
+        Ok(info)
     } else {
-        src.rewind()?;
-        internal_load_tlg(&mut src)
+        src.seek_to(0)?;
+        Ok(read_raw_header(src)?.info())
     }
 }
+
+/// Decode TLG image
+#[cfg(feature = "std")]
+pub fn load_tlg<T: Read + Seek>(src: T) -> Result<Tlg> {
+    load_tlg_with_options(src, &DecodeOptions::default())
+}
+
+/// Decode TLG image, using the given options.
+///
+/// [`Tlg::data`] is in the layout of [`DecodeOptions::pixel_format`], while
+/// [`Tlg::color`] is still the color type of the file. With the `parallel`
+/// feature, the image is decoded on several threads, as described in the
+/// [crate documentation](crate#parallel-decoding).
+#[cfg(feature = "std")]
+pub fn load_tlg_with_options<T: Read + Seek>(mut src: T, options: &DecodeOptions) -> Result<Tlg> {
+    decode(&mut src, options)
+}
+
+/// Load a TLG image into a caller-provided buffer.
+///
+/// Rows are written `stride` bytes apart, in the same pixel layout as
+/// [`Tlg::data`]. Bytes between the end of a row and the start of the next
+/// one are left untouched. Returns the image information.
+#[cfg(feature = "std")]
+pub fn load_tlg_into<T: Read + Seek>(src: T, out: &mut [u8], stride: usize) -> Result<TlgInfo> {
+    load_tlg_into_with_options(src, out, stride, &DecodeOptions::default())
+}
+
+/// Load a TLG image into a caller-provided buffer, using the given options.
+///
+/// Same as [`load_tlg_into`], with rows in the layout of
+/// [`DecodeOptions::pixel_format`].
+#[cfg(feature = "std")]
+pub fn load_tlg_into_with_options<T: Read + Seek>(
+    mut src: T,
+    out: &mut [u8],
+    stride: usize,
+    options: &DecodeOptions,
+) -> Result<TlgInfo> {
+    decode_into(&mut src, out, stride, options)
+}
+
+/// Read the information of a TLG image without decoding pixel data.
+///
+/// Parses the TLG0.0 SDS wrapper, the TLG5/TLG6 header and the chunks
+/// following the image data.
+#[cfg(feature = "std")]
+pub fn probe_tlg<T: Read + Seek>(mut src: T) -> Result<TlgInfo> {
+    probe(&mut src)
+}
+
+/// Decode a TLG image held in memory, using the given options.
+///
+/// Available without the `std` feature.
+pub fn load_tlg_from_slice(data: &[u8], options: &DecodeOptions) -> Result<Tlg> {
+    decode(&mut SliceReader::new(data), options)
+}
+
+/// Load a TLG image held in memory into a caller-provided buffer, using the
+/// given options.
+///
+/// Rows are written `stride` bytes apart, as with `load_tlg_into`. Available
+/// without the `std` feature.
+pub fn load_tlg_into_from_slice(
+    data: &[u8],
+    out: &mut [u8],
+    stride: usize,
+    options: &DecodeOptions,
+) -> Result<TlgInfo> {
+    decode_into(&mut SliceReader::new(data), out, stride, options)
+}
+
+/// Read the information of a TLG image held in memory without decoding pixel
+/// data.
+///
+/// Available without the `std` feature.
+pub fn probe_tlg_from_slice(data: &[u8]) -> Result<TlgInfo> {
+    probe(&mut SliceReader::new(data))
+}
+
+/// Allocate a buffer of `len` copies of `value`, returning an error instead
+/// of aborting if the memory is not available.
+fn alloc_filled<V: Clone>(len: usize, value: V) -> Result<Vec<V>> {
+    let mut buf = Vec::new();
+    buf.try_reserve_exact(len).map_err(|_| {
+        TlgError::Str(format!(
+            "Failed to allocate {} bytes",
+            len.saturating_mul(core::mem::size_of::<V>())
+        ))
+    })?;
+    buf.resize(len, value);
+    Ok(buf)
+}
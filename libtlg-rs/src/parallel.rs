@@ -0,0 +1,376 @@
+//! Multi-threaded decoding used by [`load_tlg`](crate::load_tlg) when the
+//! `parallel` feature is enabled. The crate documentation describes when it
+//! is used.
+//!
+//! TLG5 blocks share one LZSS dictionary, so in general a block can only be
+//! decompressed after all blocks before it. The whole image is read at once
+//! and split at the channel headers, then every compressed channel is
+//! decompressed speculatively on its own thread. A channel that only refers
+//! to bytes it wrote itself, which is always the case for files whose
+//! encoder restarts the dictionary for each block, does not depend on the
+//! preceding blocks and its result is kept. The remaining channels are
+//! decompressed again in file order with the real dictionary. Finally the
+//! inverse delta and color transforms are applied to all rows in parallel.
+//!
+//! The golomb bit streams of TLG6 stripes are independent of each other, only
+//! the reconstruction of the rows depends on the row above. Stripes are read
+//! in batches; the golomb values of a batch are decoded in parallel while the
+//! rows of the previous batch are reconstructed.
+use crate::load_tlg::{Tlg5StripeDecoder, Tlg6StripeDecoder};
+use crate::stream::Source;
+use crate::tvpgl::*;
+use crate::*;
+use overf::wrapping;
+use rayon::prelude::*;
+
+/// A channel of a TLG5 block, as stored in the file.
+struct Tlg5Channel<'a> {
+    compressed: bool,
+    data: &'a [u8],
+    /// Window position of the first decompressed byte, assuming every
+    /// preceding compressed channel fills its whole block.
+    r: usize,
+}
+
+/// Decode a TLG5 image whose compressed data is within the next `len` bytes
+/// of `src`.
+pub fn decode_tlg5<T: Source>(
+    decoder: &mut Tlg5StripeDecoder,
+    src: &mut T,
+    len: usize,
+    out: &mut [u8],
+    stride: usize,
+) -> Result<()> {
+    let width = decoder.width as usize;
+    let colors = decoder.colors as usize;
+    let row_size = width * colors;
+    let blockheight = decoder.blockheight as usize;
+    let height = decoder.height as usize;
+    let bufsize = blockheight * width + 10;
+    let start = src.position()?;
+    let mut raw = Vec::new();
+    src.read_exact_vec(&mut raw, len)?;
+
+    // Channels are found from their headers, as the serial decoder does, so
+    // the block size table is never trusted.
+    let mut channels = Vec::new();
+    let mut pos = 0;
+    let mut r = decoder.r;
+    for y_blk in (0..height).step_by(blockheight) {
+        let block_len = (blockheight.min(height - y_blk)) * width;
+        for _ in 0..colors {
+            let header = raw.get(pos..pos + 5).ok_or(TlgError::UnexpectedEof)?;
+            let size = u32::from_le_bytes([header[1], header[2], header[3], header[4]]) as usize;
+            if size > bufsize {
+                return Err(TlgError::IndexOutOfRange);
+            }
+            let data = raw
+                .get(pos + 5..pos + 5 + size)
+                .ok_or(TlgError::UnexpectedEof)?;
+            let compressed = header[0] == 0;
+            if let Some(layout) = &mut decoder.layout {
+                layout.raw.push(!compressed);
+            }
+            channels.push(Tlg5Channel {
+                compressed,
+                data,
+                r,
+            });
+            if compressed {
+                r = (r + block_len) & 4095;
+            }
+            pos += 5 + size;
+        }
+    }
+    src.seek_to(start + pos as u64)?;
+
+    let speculative: Vec<(Vec<u8>, Option<usize>)> = channels
+        .par_iter()
+        .map(|ch| {
+            let mut buf = vec![0u8; bufsize];
+            let len = if ch.compressed {
+                tlg5_decompress_slide_independent(&mut buf, ch.data, ch.r)
+            } else {
+                None
+            };
+            (buf, len)
+        })
+        .collect();
+
+    let mut bufs = Vec::with_capacity(channels.len());
+    for (ch, (mut buf, len)) in channels.iter().zip(speculative) {
+        if !ch.compressed {
+            buf.get_mut(..ch.data.len())
+                .ok_or(TlgError::IndexOutOfRange)?
+                .copy_from_slice(ch.data);
+        } else if let (Some(len), true) = (len, ch.r == decoder.r) {
+            // Replay the tail of the output into the dictionary.
+            for (j, &c) in buf[..len].iter().enumerate().skip(len.saturating_sub(4096)) {
+                decoder.text[(decoder.r + j) & 4095] = c;
+            }
+            decoder.r = (decoder.r + len) & 4095;
+        } else {
+            decoder.r = tlg5_decompress_slide(
+                &mut buf,
+                ch.data,
+                ch.data.len(),
+                &mut decoder.text,
+                decoder.r,
+            )?;
+        }
+        bufs.push(buf);
+    }
+    decoder.y = decoder.height;
+
+    // Horizontal pass: every row is composed as if the row above was zero.
+    let zeroline = vec![0u8; row_size];
+    out.par_chunks_mut(stride)
+        .take(height)
+        .enumerate()
+        .for_each(|(y, row)| {
+            let row = &mut row[..row_size];
+            let blk = y / blockheight;
+            let off = (y % blockheight) * width;
+            let b = &bufs[blk * colors..(blk + 1) * colors];
+            match decoder.color {
+                TlgColorType::Bgr24 => {
+                    let buf = [&b[0][off..], &b[1][off..], &b[2][off..]];
+                    tlg5_compose_colors3(row, &zeroline, &buf, width as u32);
+                }
+                TlgColorType::Bgra32 => {
+                    let buf = [&b[0][off..], &b[1][off..], &b[2][off..], &b[3][off..]];
+                    tlg5_compose_colors4(row, &zeroline, &buf, width as u32);
+                }
+                TlgColorType::Grayscale8 => {
+                    tlg5_compose_colors1(row, &zeroline, &[&b[0][off..]], width as u32);
+                }
+            }
+        });
+
+    // Vertical pass: a prefix sum over the rows, computed per band of rows
+    // and then corrected with the last row of the preceding bands.
+    let bands = rayon::current_num_threads() * 4;
+    let band_rows = height.div_ceil(bands).max(1);
+    let out = &mut out[..(height - 1) * stride + row_size];
+    out.par_chunks_mut(band_rows * stride).for_each(|band| {
+        for y in 1..band.len().div_ceil(stride) {
+            let (prev, cur) = band.split_at_mut(y * stride);
+            add_row(&mut cur[..row_size], &prev[(y - 1) * stride..]);
+        }
+    });
+    let mut carries = vec![zeroline];
+    for band in out.chunks(band_rows * stride) {
+        let last = (band.len().div_ceil(stride) - 1) * stride;
+        let mut carry = carries.last().unwrap().clone();
+        add_row(&mut carry, &band[last..last + row_size]);
+        carries.push(carry);
+    }
+    out.par_chunks_mut(band_rows * stride)
+        .zip(carries.par_iter())
+        .skip(1)
+        .for_each(|(band, carry)| {
+            for row in band.chunks_mut(stride) {
+                add_row(&mut row[..row_size], carry);
+            }
+        });
+    Ok(())
+}
+
+/// Golomb bit streams of one TLG6 stripe, one per channel.
+struct Tlg6Stripe {
+    pixel_count: usize,
+    bit_pools: Vec<Vec<u8>>,
+}
+
+pub fn decode_tlg6<T: Source>(
+    decoder: &mut Tlg6StripeDecoder,
+    src: &mut T,
+    out: &mut [u8],
+    stride: usize,
+) -> Result<()> {
+    let stripe_height = decoder.stripe_height();
+    let stripe_size = stride * stripe_height as usize;
+    let batch_size = rayon::current_num_threads() * 2;
+    let mut stripes = (decoder.y..decoder.height).step_by(stripe_height as usize);
+    let mut out = out.chunks_mut(stripe_size);
+
+    let mut read_batch = |decoder: &Tlg6StripeDecoder| -> Result<Vec<Tlg6Stripe>> {
+        let mut batch = Vec::with_capacity(batch_size);
+        for y in stripes.by_ref().take(batch_size) {
+            let mut bit_pools = Vec::with_capacity(decoder.colors as usize);
+            for _ in 0..decoder.colors {
+                let mut bit_pool = Vec::new();
+                decoder.read_bit_stream(src, &mut bit_pool)?;
+                bit_pools.push(bit_pool);
+            }
+            let pixel_count = decoder.stripe_pixel_count(y);
+            batch.push(Tlg6Stripe {
+                pixel_count,
+                bit_pools,
+            });
+        }
+        Ok(batch)
+    };
+    let colors = decoder.colors;
+    let decode_batch = |batch: Vec<Tlg6Stripe>| -> Result<Vec<Vec<u32>>> {
+        batch
+            .into_par_iter()
+            .map(|stripe| {
+                let mut pixelbuf = vec![0u32; stripe.pixel_count + 1];
+                for (c, bit_pool) in stripe.bit_pools.iter().enumerate() {
+                    tlg6_decode_golomb_values(
+                        &mut pixelbuf,
+                        stripe.pixel_count,
+                        bit_pool,
+                        c == 0 && colors != 1,
+                        c as u8,
+                    )?;
+                }
+                Ok(pixelbuf)
+            })
+            .collect()
+    };
+
+    let batch = read_batch(decoder)?;
+    let mut pixelbufs = decode_batch(batch)?;
+    while !pixelbufs.is_empty() {
+        let batch = read_batch(decoder)?;
+        let (composed, next) = rayon::join(
+            || -> Result<()> {
+                for (pixelbuf, stripe) in pixelbufs.iter().zip(out.by_ref()) {
+                    decoder.compose_stripe(pixelbuf, stripe, stride)?;
+                }
+                Ok(())
+            },
+            || decode_batch(batch),
+        );
+        composed?;
+        pixelbufs = next?;
+    }
+    Ok(())
+}
+
+fn add_row(row: &mut [u8], other: &[u8]) {
+    for (a, &b) in row.iter_mut().zip(other) {
+        wrapping! { *a += b };
+    }
+}
+
+#[cfg(all(test, feature = "encode"))]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const WIDTH: u32 = 37;
+    const HEIGHT: u32 = 29;
+
+    /// Noise in the left half and a gradient in the right half.
+    fn image(color: TlgColorType) -> Tlg {
+        let colors = match color {
+            TlgColorType::Grayscale8 => 1,
+            TlgColorType::Bgr24 => 3,
+            TlgColorType::Bgra32 => 4,
+        };
+        let mut x = 0x9e37_79b9u32;
+        let mut data = Vec::new();
+        for y in 0..HEIGHT {
+            for px in 0..WIDTH {
+                for c in 0..colors {
+                    x = x.wrapping_mul(1664525).wrapping_add(1013904223);
+                    data.push(if px < WIDTH / 2 {
+                        (x >> 24) as u8
+                    } else {
+                        (px * 3 + y * 5 + c * 60) as u8
+                    });
+                }
+            }
+        }
+        Tlg {
+            tags: TlgTags::new(),
+            chunks: Vec::new(),
+            version: 5,
+            width: WIDTH,
+            height: HEIGHT,
+            color,
+            data,
+            tlg5_layout: None,
+        }
+    }
+
+    fn load_with_threads(data: &[u8], threads: usize) -> Result<Tlg> {
+        load_with_options(data, threads, &DecodeOptions::default())
+    }
+
+    fn load_with_options(data: &[u8], threads: usize, options: &DecodeOptions) -> Result<Tlg> {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap()
+            .install(|| load_tlg_with_options(Cursor::new(data), options))
+    }
+
+    #[test]
+    fn tlg5_matches_serial() {
+        let colors = [
+            TlgColorType::Grayscale8,
+            TlgColorType::Bgr24,
+            TlgColorType::Bgra32,
+        ];
+        for color in colors {
+            let img = image(color);
+            for block_height in [1, 3, 4, HEIGHT + 5] {
+                let mut options = EncodeOptions::default();
+                options.tlg5.block_height = block_height;
+                let mut data = Vec::new();
+                save_tlg_with_options(&img, &mut data, &options).unwrap();
+                let serial = load_with_threads(&data, 1).unwrap();
+                let parallel = load_with_threads(&data, 4).unwrap();
+                assert!(serial.data == img.data, "{color:?} {block_height}");
+                assert!(parallel.data == img.data, "{color:?} {block_height}");
+                // The block size table is not needed by either path.
+                let blockcount = HEIGHT.div_ceil(block_height.min(HEIGHT)) as usize;
+                data[24..24 + blockcount * 4].fill(0);
+                let parallel = load_with_threads(&data, 4).unwrap();
+                assert!(parallel.data == img.data, "{color:?} {block_height}");
+            }
+        }
+    }
+
+    #[test]
+    fn limits_are_reported() {
+        let mut img = image(TlgColorType::Bgra32);
+        img.tags.append("mode", "alpha");
+        img.chunks.push(TlgChunk {
+            name: *b"abcd",
+            data: vec![1; 8],
+        });
+        for version in [5, 6] {
+            img.version = version;
+            let mut data = Vec::new();
+            save_tlg(&img, &mut data).unwrap();
+            load_with_threads(&data, 4).unwrap();
+            for field in [
+                "max_pixels",
+                "max_alloc_bytes",
+                "max_tag_bytes",
+                "max_chunks",
+            ] {
+                let mut options = DecodeOptions::default();
+                let limits = &mut options.limits;
+                match field {
+                    "max_pixels" => limits.max_pixels = (WIDTH * HEIGHT - 1) as u64,
+                    "max_alloc_bytes" => limits.max_alloc_bytes = 100,
+                    "max_tag_bytes" => limits.max_tag_bytes = 14,
+                    _ => limits.max_chunks = 1,
+                }
+                for threads in [1, 4] {
+                    let result = load_with_options(&data, threads, &options);
+                    assert!(
+                        matches!(result, Err(TlgError::LimitExceeded(f)) if f == field),
+                        "{version} {field} {threads}"
+                    );
+                }
+            }
+        }
+    }
+}
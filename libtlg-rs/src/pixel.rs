@@ -0,0 +1,128 @@
+//! Conversion of decoded rows to the requested [`PixelFormat`].
+use crate::*;
+
+/// Swap the red and blue channels of data in the native layout of `color`,
+/// converting between BGR(A) and RGB(A). Grayscale data is left unchanged.
+pub fn swap_red_blue(data: &mut [u8], color: TlgColorType) {
+    let step = match color {
+        TlgColorType::Grayscale8 => return,
+        TlgColorType::Bgr24 => 3,
+        TlgColorType::Bgra32 => 4,
+    };
+    for px in data.chunks_exact_mut(step) {
+        px.swap(0, 2);
+    }
+}
+
+/// Write a row of pixels stored in the native layout of `color` to `out`.
+pub fn write_native_row(format: PixelFormat, color: TlgColorType, out: &mut [u8], row: &[u8]) {
+    match color {
+        TlgColorType::Grayscale8 => {
+            write_row(format, color, out, row.iter().map(|&v| [v, v, v, 255]))
+        }
+        TlgColorType::Bgr24 => write_row(
+            format,
+            color,
+            out,
+            row.chunks_exact(3).map(|p| [p[0], p[1], p[2], 255]),
+        ),
+        TlgColorType::Bgra32 => write_row(
+            format,
+            color,
+            out,
+            row.chunks_exact(4).map(|p| [p[0], p[1], p[2], p[3]]),
+        ),
+    }
+}
+
+/// Write a row of pixels stored as little-endian BGRA words to `out`.
+///
+/// Only the channels present in `color` are read.
+pub fn write_word_row(format: PixelFormat, color: TlgColorType, out: &mut [u8], row: &[u32]) {
+    match color {
+        TlgColorType::Grayscale8 => write_row(
+            format,
+            color,
+            out,
+            row.iter().map(|&p| {
+                let v = p as u8;
+                [v, v, v, 255]
+            }),
+        ),
+        TlgColorType::Bgr24 => write_row(
+            format,
+            color,
+            out,
+            row.iter().map(|&p| {
+                let [b, g, r, _] = p.to_le_bytes();
+                [b, g, r, 255]
+            }),
+        ),
+        TlgColorType::Bgra32 => write_row(format, color, out, row.iter().map(|p| p.to_le_bytes())),
+    }
+}
+
+fn write_row<I: Iterator<Item = [u8; 4]>>(
+    format: PixelFormat,
+    color: TlgColorType,
+    out: &mut [u8],
+    pixels: I,
+) {
+    match format {
+        PixelFormat::Native => match color {
+            TlgColorType::Grayscale8 => {
+                for (o, [v, ..]) in out.iter_mut().zip(pixels) {
+                    *o = v;
+                }
+            }
+            TlgColorType::Bgr24 => {
+                for (o, [b, g, r, _]) in out.chunks_exact_mut(3).zip(pixels) {
+                    o.copy_from_slice(&[b, g, r]);
+                }
+            }
+            TlgColorType::Bgra32 => {
+                for (o, p) in out.chunks_exact_mut(4).zip(pixels) {
+                    o.copy_from_slice(&p);
+                }
+            }
+        },
+        PixelFormat::Rgba8 => {
+            for (o, [b, g, r, a]) in out.chunks_exact_mut(4).zip(pixels) {
+                o.copy_from_slice(&[r, g, b, a]);
+            }
+        }
+        PixelFormat::Rgb8 => {
+            for (o, [b, g, r, _]) in out.chunks_exact_mut(3).zip(pixels) {
+                o.copy_from_slice(&[r, g, b]);
+            }
+        }
+        PixelFormat::Bgra8 => {
+            for (o, p) in out.chunks_exact_mut(4).zip(pixels) {
+                o.copy_from_slice(&p);
+            }
+        }
+        PixelFormat::Bgrx8 => {
+            for (o, [b, g, r, _]) in out.chunks_exact_mut(4).zip(pixels) {
+                o.copy_from_slice(&[b, g, r, 255]);
+            }
+        }
+        PixelFormat::Rgba8Premultiplied => {
+            for (o, [b, g, r, a]) in out.chunks_exact_mut(4).zip(pixels) {
+                o.copy_from_slice(&[premultiply(r, a), premultiply(g, a), premultiply(b, a), a]);
+            }
+        }
+        PixelFormat::GrayAlpha8 => {
+            for (o, [b, g, r, a]) in out.chunks_exact_mut(2).zip(pixels) {
+                o.copy_from_slice(&[luma(b, g, r), a]);
+            }
+        }
+    }
+}
+
+fn premultiply(c: u8, a: u8) -> u8 {
+    ((c as u32 * a as u32 + 127) / 255) as u8
+}
+
+fn luma(b: u8, g: u8, r: u8) -> u8 {
+    ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114 + 500) / 1000) as u8
+}
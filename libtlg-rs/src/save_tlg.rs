@@ -1,11 +1,59 @@
 use super::*;
 use crate::stream::*;
-use crate::tlg5_saver::save_tlg5;
-use std::io::{Seek, Write};
+use crate::tlg5_saver::Tlg5Stream;
+use crate::tlg6_saver::{Tlg6Stream, encode_tlg6};
+use std::io::Write;
+
+/// Raw TLG5/TLG6 stream, encoded far enough to know its size.
+enum RawTlg<'a> {
+    V5(Tlg5Stream<'a>),
+    V6(Tlg6Stream<'a>),
+}
+
+impl<'a> RawTlg<'a> {
+    fn new(img: &'a Tlg, options: &'a EncodeOptions) -> Result<Self> {
+        match img.version {
+            5 => Ok(RawTlg::V5(Tlg5Stream::new(img, &options.tlg5)?)),
+            6 => Ok(RawTlg::V6(encode_tlg6(img, options)?)),
+            _ => Err(TlgError::EncodeError(format!(
+                "Unsupported TLG version: {}",
+                img.version
+            ))),
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            RawTlg::V5(s) => s.size(),
+            RawTlg::V6(s) => s.size(),
+        }
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        match self {
+            RawTlg::V5(s) => s.write(writer),
+            RawTlg::V6(s) => s.write(writer),
+        }
+    }
+}
 
 /// Encode TLG image
+///
+/// The writer does not need to be seekable. TLG5 blocks are compressed twice,
+/// first to compute the block size table preceding them, so only one block is
+/// kept in memory. TLG6 bit streams are kept in memory until they are written.
+#[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
+pub fn save_tlg<W: Write>(img: &Tlg, writer: W) -> Result<()> {
+    save_tlg_with_options(img, writer, &EncodeOptions::default())
+}
+
+/// Encode TLG image with the given options
 #[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
-pub fn save_tlg<W: Write + Seek>(img: &Tlg, mut writer: W) -> Result<()> {
+pub fn save_tlg_with_options<W: Write>(
+    img: &Tlg,
+    mut writer: W,
+    options: &EncodeOptions,
+) -> Result<()> {
     let colors = match img.color {
         TlgColorType::Bgra32 => 4,
         TlgColorType::Bgr24 => 3,
@@ -19,35 +67,48 @@ pub fn save_tlg<W: Write + Seek>(img: &Tlg, mut writer: W) -> Result<()> {
             img.data.len()
         )));
     }
-    if img.tags.is_empty() {
-        if img.version == 5 {
-            return save_tlg5(img, &mut writer);
-        } else {
-            return Err(TlgError::EncodeError(format!(
-                "Unsupported TLG version: {}",
-                img.version
-            )));
-        }
+    if img.width == 0 || img.height == 0 {
+        return Err(TlgError::EncodeError(
+            "Image width and height must be non-zero".to_string(),
+        ));
+    }
+    let raw = RawTlg::new(img, options)?;
+    if img.tags.is_empty() && img.chunks.is_empty() {
+        return raw.write(&mut writer);
     }
+    let size = u32::try_from(raw.size())
+        .map_err(|_| TlgError::EncodeError("Image data is too large".to_string()))?;
     writer.write_all(b"TLG0.0\x00sds\x1a")?;
-    let rawlenpos = writer.stream_position()?;
-    writer.write_u32(0)?; // Placeholder for raw data length
-    if img.version == 5 {
-        save_tlg5(img, &mut writer)?;
-    } else {
-        return Err(TlgError::EncodeError(format!(
-            "Unsupported TLG version: {}",
-            img.version
-        )));
+    writer.write_u32(size)?;
+    raw.write(&mut writer)?;
+    let mut tags = img.tags.iter();
+    let mut markers = img.chunks.iter().filter(|c| &c.name == b"tags").count();
+    if markers == 0 && !img.tags.is_empty() {
+        write_tags(&mut writer, tags.by_ref())?;
     }
-    let pos_save = writer.stream_position()?;
-    writer.seek(std::io::SeekFrom::Start(rawlenpos))?;
-    let size = pos_save - rawlenpos - 4;
-    writer.write_u32(size as u32)?;
-    writer.seek(std::io::SeekFrom::Start(pos_save))?;
-    writer.write_all(b"tags")?;
+    for chunk in &img.chunks {
+        if &chunk.name != b"tags" {
+            write_chunk(&mut writer, &chunk.name, &chunk.data)?;
+            continue;
+        }
+        markers -= 1;
+        let count = match <[u8; 4]>::try_from(chunk.data.as_slice()) {
+            _ if markers == 0 => usize::MAX,
+            Ok(count) => u32::from_le_bytes(count) as usize,
+            Err(_) => 0,
+        };
+        write_tags(&mut writer, tags.by_ref().take(count))?;
+    }
+    Ok(())
+}
+
+/// Write a `tags` chunk holding `tags`.
+fn write_tags<'a, W: Write>(
+    writer: &mut W,
+    tags: impl Iterator<Item = (&'a [u8], &'a [u8])>,
+) -> Result<()> {
     let mut ss = Vec::new();
-    for (k, v) in &img.tags {
+    for (k, v) in tags {
         ss.write_all(k.len().to_string().as_bytes())?;
         ss.write_all(b":")?;
         ss.write_all(k)?;
@@ -57,7 +118,18 @@ pub fn save_tlg<W: Write + Seek>(img: &Tlg, mut writer: W) -> Result<()> {
         ss.write_all(v)?;
         ss.write_all(b",")?;
     }
-    writer.write_u32(ss.len() as u32)?;
-    writer.write_all(&ss)?;
+    write_chunk(writer, b"tags", &ss)
+}
+
+fn write_chunk<W: Write>(writer: &mut W, name: &[u8; 4], data: &[u8]) -> Result<()> {
+    let size = u32::try_from(data.len()).map_err(|_| {
+        TlgError::EncodeError(format!(
+            "Chunk {} is too large",
+            String::from_utf8_lossy(name)
+        ))
+    })?;
+    writer.write_all(name)?;
+    writer.write_u32(size)?;
+    writer.write_all(data)?;
     Ok(())
 }
@@ -1,37 +1,140 @@
 //! Slide Compressor
+use crate::LzssEffort;
 #[derive(Clone, Copy)]
 struct Chain {
     prev: i32,
     next: i32,
 }
 
-const SLIDE_N: usize = 4096;
+pub const SLIDE_N: usize = 4096;
+/// Number of match candidates tried with [`LzssEffort::Fast`].
+const FAST_SEARCH_LIMIT: usize = 64;
 const SLIDE_M: usize = 18 + 255;
 const TEXT_SIZE: usize = SLIDE_N + SLIDE_M;
 const MAP_SIZE: usize = 256 * 256;
 
+/// Bits used by a literal: a flag and a byte.
+const LITERAL_BITS: u32 = 9;
+/// Bits used by a match of 3 to 17 bytes: a flag and 2 bytes.
+const MATCH_BITS: u32 = 17;
+/// Bits used by a longer match: a flag and 3 bytes.
+const LONG_MATCH_BITS: u32 = 25;
+
+/// Groups literals and matches behind their flag byte.
+struct CodeWriter {
+    code: [u8; 40],
+    codeptr: usize,
+    mask: u8,
+}
+
+impl CodeWriter {
+    fn new() -> Self {
+        Self {
+            code: [0; 40],
+            codeptr: 1,
+            mask: 1,
+        }
+    }
+
+    fn push_literal(&mut self, c: u8, output: &mut Vec<u8>) {
+        self.code[self.codeptr] = c;
+        self.codeptr += 1;
+        self.next(output);
+    }
+
+    fn push_match(&mut self, len: i32, pos: i32, output: &mut Vec<u8>) {
+        self.code[0] |= self.mask;
+        self.code[self.codeptr] = (pos & 0xff) as u8;
+        if len >= 18 {
+            self.code[self.codeptr + 1] = (((pos & 0xf00) >> 8) as u8) | 0xf0;
+            self.code[self.codeptr + 2] = (len - 18) as u8;
+            self.codeptr += 3;
+        } else {
+            self.code[self.codeptr + 1] = (((pos & 0xf00) >> 8) as u8) | (((len - 3) as u8) << 4);
+            self.codeptr += 2;
+        }
+        self.next(output);
+    }
+
+    fn next(&mut self, output: &mut Vec<u8>) {
+        self.mask <<= 1;
+        if self.mask == 0 {
+            output.extend_from_slice(&self.code[..self.codeptr]);
+            self.mask = 1;
+            self.codeptr = 1;
+            self.code[0] = 0;
+        }
+    }
+
+    fn finish(self, output: &mut Vec<u8>) {
+        if self.mask != 1 {
+            output.extend_from_slice(&self.code[..self.codeptr]);
+        }
+    }
+}
+
+/// A step of [`SlideCompressor::advance`], with what it overwrote.
+#[derive(Clone, Copy)]
+struct Step {
+    s: i32,
+    c: u8,
+    /// Links of `s - 1` and `s` when they were deleted
+    prev_chain: Chain,
+    chain: Chain,
+}
+
+/// Number of steps whose journal takes as much memory as a copy of the
+/// tables. Past it, the state saved by [`SlideCompressor::store`] is kept as
+/// a copy instead.
+const JOURNAL_LIMIT: usize =
+    (TEXT_SIZE + MAP_SIZE * core::mem::size_of::<i32>() + SLIDE_N * core::mem::size_of::<Chain>())
+        / core::mem::size_of::<Step>();
+
+/// Copy of the tables of a [`SlideCompressor`].
+struct Snapshot {
+    text: Vec<u8>,
+    map: Vec<i32>,
+    chains: Vec<Chain>,
+}
+
 pub struct SlideCompressor {
     text: Vec<u8>,
     map: Vec<i32>,
     chains: Vec<Chain>,
-    text2: Vec<u8>,
-    map2: Vec<i32>,
-    chains2: Vec<Chain>,
+    /// Steps since the last [`Self::store`], undone by [`Self::restore`]
+    journal: Vec<Step>,
+    journaling: bool,
+    /// State saved by the last [`Self::store`], once the journal is too long
+    snapshot: Option<Snapshot>,
     s: i32,
     s2: i32,
+    /// Maximum number of candidates tried for a match
+    search_limit: usize,
+    /// Use optimal parsing instead of taking the longest match
+    optimal: bool,
 }
 
 impl SlideCompressor {
     pub fn new() -> Self {
+        Self::with_text(&[0; SLIDE_N])
+    }
+
+    /// Create a compressor whose dictionary starts with `init` instead of zeros.
+    pub fn with_text(init: &[u8; SLIDE_N]) -> Self {
+        let mut text = vec![0; TEXT_SIZE];
+        text[..SLIDE_N].copy_from_slice(init);
+        text[SLIDE_N..].copy_from_slice(&init[..SLIDE_M]);
         let mut data = Self {
-            text: vec![0; TEXT_SIZE],
+            text,
             map: vec![-1; MAP_SIZE],
             chains: vec![Chain { prev: -1, next: -1 }; SLIDE_N],
-            text2: vec![0; TEXT_SIZE],
-            map2: vec![0; MAP_SIZE],
-            chains2: vec![Chain { prev: 0, next: 0 }; SLIDE_N],
+            journal: Vec::new(),
+            journaling: false,
+            snapshot: None,
             s: 0,
             s2: 0,
+            search_limit: usize::MAX,
+            optimal: false,
         };
         for i in (0..SLIDE_N).rev() {
             data.add_map(i as i32);
@@ -39,6 +142,15 @@ impl SlideCompressor {
         data
     }
 
+    /// Set the effort spent looking for matches.
+    pub fn set_effort(&mut self, effort: LzssEffort) {
+        self.search_limit = match effort {
+            LzssEffort::Fast => FAST_SEARCH_LIMIT,
+            _ => usize::MAX,
+        };
+        self.optimal = effort == LzssEffort::Optimal;
+    }
+
     fn add_map(&mut self, p: i32) {
         let place = self.text[p as usize] as i32
             + ((self.text[(p as usize + 1) & (SLIDE_N - 1)] as i32) << 8);
@@ -53,6 +165,22 @@ impl SlideCompressor {
         }
     }
 
+    /// Put `p` back between the neighbours it had before [`Self::delete_map`].
+    fn relink(&mut self, p: i32, chain: Chain) {
+        let p_us = p as usize;
+        self.chains[p_us] = chain;
+        if chain.next != -1 {
+            self.chains[chain.next as usize].prev = p;
+        }
+        if chain.prev != -1 {
+            self.chains[chain.prev as usize].next = p;
+        } else {
+            let place =
+                self.text[p_us] as i32 + ((self.text[(p_us + 1) & (SLIDE_N - 1)] as i32) << 8);
+            self.map[place as usize] = p;
+        }
+    }
+
     fn delete_map(&mut self, p: i32) {
         let p_us = p as usize;
         let mut n = self.chains[p_us].next;
@@ -88,7 +216,9 @@ impl SlideCompressor {
             return (0, 0);
         }
         curlen -= 1;
-        while head != -1 {
+        let mut tries = 0;
+        while head != -1 && tries < self.search_limit {
+            tries += 1;
             let place_org = head;
             if s == place_org || s == ((place_org + 1) & (SLIDE_N as i32 - 1)) {
                 head = self.chains[place_org as usize].next;
@@ -123,7 +253,8 @@ impl SlideCompressor {
             if matchlen > maxlen {
                 maxlen = matchlen;
                 pos = place_org;
-                if matchlen == SLIDE_M as i32 {
+                // No later candidate can be longer.
+                if matchlen == (SLIDE_M as i32).min(curlen) {
                     return (maxlen, pos);
                 }
             }
@@ -132,97 +263,270 @@ impl SlideCompressor {
         (maxlen, pos)
     }
 
+    /// Move byte `c` into the window at position `s`, returning the next
+    /// position.
+    fn advance(&mut self, s: i32, c: u8) -> i32 {
+        let s_prev = (s - 1) & (SLIDE_N as i32 - 1);
+        let prev_chain = self.chains[s_prev as usize];
+        self.delete_map(s_prev);
+        let chain = self.chains[s as usize];
+        self.delete_map(s);
+        if self.journaling {
+            self.journal.push(Step {
+                s,
+                c: self.text[s as usize],
+                prev_chain,
+                chain,
+            });
+            if self.journal.len() > JOURNAL_LIMIT {
+                self.take_snapshot();
+            }
+        }
+        if (s as usize) < SLIDE_M - 1 {
+            self.text[(s as usize) + SLIDE_N] = c;
+        }
+        self.text[s as usize] = c;
+        self.add_map(s_prev);
+        self.add_map(s);
+        (s + 1) & (SLIDE_N as i32 - 1)
+    }
+
+    /// Compress `input`, returning the size of `output`.
     pub fn encode_into(&mut self, input: &[u8], output: &mut Vec<u8>) -> usize {
+        if self.optimal {
+            self.encode_optimal(input, output)
+        } else {
+            self.encode_greedy(input, output)
+        }
+    }
+
+    /// Compress `input`, taking the longest match at each position.
+    fn encode_greedy(&mut self, input: &[u8], output: &mut Vec<u8>) -> usize {
         if input.is_empty() {
             return 0;
         }
-        let mut code = [0u8; 40];
-        let mut codeptr: usize = 1;
-        let mut mask: u8 = 1;
-        code[0] = 0;
+        let mut code = CodeWriter::new();
         let mut idx: usize = 0;
-        let mut remain = input.len();
         let mut s = self.s;
-        while remain > 0 {
-            let (len, pos) = {
-                let (l, p) = self.get_match(&input[idx..], s);
-                (l, p)
-            };
+        while idx < input.len() {
+            let (len, pos) = self.get_match(&input[idx..], s);
             if len >= 3 {
-                code[0] |= mask;
-                if len >= 18 {
-                    code[codeptr] = (pos & 0xff) as u8;
-                    codeptr += 1;
-                    code[codeptr] = (((pos & 0xf00) >> 8) as u8) | 0xf0;
-                    codeptr += 1;
-                    code[codeptr] = (len - 18) as u8;
-                    codeptr += 1;
-                } else {
-                    code[codeptr] = (pos & 0xff) as u8;
-                    codeptr += 1;
-                    code[codeptr] = (((pos & 0xf00) >> 8) as u8) | (((len - 3) as u8) << 4);
-                    codeptr += 1;
-                }
-                let mut l = len as usize;
-                while l > 0 {
-                    let c = input[idx];
-                    idx += 1;
-                    remain -= 1;
-                    l -= 1;
-                    let s_prev = (s - 1) & (SLIDE_N as i32 - 1);
-                    self.delete_map(s_prev);
-                    self.delete_map(s);
-                    if (s as usize) < SLIDE_M - 1 {
-                        self.text[(s as usize) + SLIDE_N] = c;
-                    }
-                    self.text[s as usize] = c;
-                    self.add_map(s_prev);
-                    self.add_map(s);
-                    s = (s + 1) & (SLIDE_N as i32 - 1);
+                code.push_match(len, pos, output);
+                for &c in &input[idx..idx + len as usize] {
+                    s = self.advance(s, c);
                 }
+                idx += len as usize;
             } else {
                 let c = input[idx];
+                s = self.advance(s, c);
+                code.push_literal(c, output);
                 idx += 1;
-                remain -= 1;
-                let s_prev = (s - 1) & (SLIDE_N as i32 - 1);
-                self.delete_map(s_prev);
-                self.delete_map(s);
-                if (s as usize) < SLIDE_M - 1 {
-                    self.text[(s as usize) + SLIDE_N] = c;
+            }
+        }
+        code.finish(output);
+        self.s = s;
+        output.len()
+    }
+
+    /// Compress `input`, choosing the literals and matches that give the
+    /// smallest output.
+    ///
+    /// The window after each byte is the same however the preceding bytes
+    /// were coded, so a match is found at every position first, then the
+    /// cheapest way to code the rest of the input is computed backwards from
+    /// the end.
+    ///
+    /// A long match also gives a match one byte shorter at the next position,
+    /// which is used instead of searching again. The positions a greedy parse
+    /// would code are always searched, so the output is never larger than
+    /// the one of [`Self::encode_greedy`].
+    fn encode_optimal(&mut self, input: &[u8], output: &mut Vec<u8>) -> usize {
+        if input.is_empty() {
+            return 0;
+        }
+        let n = input.len();
+        let mut matches: Vec<(i32, i32)> = Vec::with_capacity(n);
+        let mut s = self.s;
+        let mut greedy_next = 0;
+        for (idx, &c) in input.iter().enumerate() {
+            let m = match matches.last() {
+                Some(&(len, pos)) if idx != greedy_next && len > 18 => {
+                    (len - 1, (pos + 1) & (SLIDE_N as i32 - 1))
                 }
-                self.text[s as usize] = c;
-                self.add_map(s_prev);
-                self.add_map(s);
-                s = (s + 1) & (SLIDE_N as i32 - 1);
-                code[codeptr] = c;
-                codeptr += 1;
+                _ => self.get_match(&input[idx..], s),
+            };
+            if idx == greedy_next {
+                greedy_next += if m.0 >= 3 { m.0 as usize } else { 1 };
             }
-            mask <<= 1;
-            if mask == 0 {
-                output.extend_from_slice(&code[..codeptr]);
-                mask = 1;
-                codeptr = 1;
-                code[0] = 0;
+            matches.push(m);
+            s = self.advance(s, c);
+        }
+        self.s = s;
+        // Bits needed to code input[idx..] and the length of the first code,
+        // 1 for a literal.
+        let mut cost = vec![0u32; n + 1];
+        let mut step = vec![1usize; n];
+        for idx in (0..n).rev() {
+            let mut best = cost[idx + 1] + LITERAL_BITS;
+            let (len, _) = matches[idx];
+            for l in 3..=len as usize {
+                let bits = cost[idx + l] + if l >= 18 { LONG_MATCH_BITS } else { MATCH_BITS };
+                if bits <= best {
+                    best = bits;
+                    step[idx] = l;
+                }
             }
+            cost[idx] = best;
         }
-        if mask != 1 {
-            output.extend_from_slice(&code[..codeptr]);
+        let mut code = CodeWriter::new();
+        let mut idx = 0;
+        while idx < n {
+            let l = step[idx];
+            if l == 1 {
+                code.push_literal(input[idx], output);
+            } else {
+                code.push_match(l as i32, matches[idx].1, output);
+            }
+            idx += l;
         }
-        self.s = s;
+        code.finish(output);
         output.len()
     }
 
+    /// Save the current state, to be brought back by [`Self::restore`].
+    ///
+    /// Only the bytes moved into the window afterwards are recorded, so this
+    /// is cheap compared to copying the tables. Once the record would take
+    /// more memory than the tables, they are copied instead.
     pub fn store(&mut self) {
         self.s2 = self.s;
-        self.text2.copy_from_slice(&self.text);
-        self.map2.copy_from_slice(&self.map);
-        self.chains2.copy_from_slice(&self.chains);
+        self.journal.clear();
+        self.snapshot = None;
+        self.journaling = true;
+    }
+
+    /// Replace the journal with a copy of the tables as they were at the last
+    /// [`Self::store`].
+    fn take_snapshot(&mut self) {
+        let text = self.text.clone();
+        let map = self.map.clone();
+        let chains = self.chains.clone();
+        self.undo_journal();
+        self.snapshot = Some(Snapshot {
+            text: core::mem::replace(&mut self.text, text),
+            map: core::mem::replace(&mut self.map, map),
+            chains: core::mem::replace(&mut self.chains, chains),
+        });
+        self.journaling = false;
     }
 
+    /// Go back to the state saved by the last [`Self::store`].
     pub fn restore(&mut self) {
         self.s = self.s2;
-        self.text.copy_from_slice(&self.text2);
-        self.map.copy_from_slice(&self.map2);
-        self.chains.copy_from_slice(&self.chains2);
+        if let Some(snapshot) = self.snapshot.take() {
+            self.text = snapshot.text;
+            self.map = snapshot.map;
+            self.chains = snapshot.chains;
+        } else {
+            self.undo_journal();
+        }
+        self.journaling = false;
+    }
+
+    fn undo_journal(&mut self) {
+        while let Some(step) = self.journal.pop() {
+            // Undo the steps of advance in reverse order. `s - 1` and `s`
+            // were added last, so they are at the head of their chains.
+            let s_prev = (step.s - 1) & (SLIDE_N as i32 - 1);
+            self.delete_map(step.s);
+            self.delete_map(s_prev);
+            let s_us = step.s as usize;
+            if s_us < SLIDE_M - 1 {
+                self.text[s_us + SLIDE_N] = step.c;
+            }
+            self.text[s_us] = step.c;
+            self.relink(step.s, step.chain);
+            self.relink(s_prev, step.prev_chain);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Noise alternating with runs, so that both literals and matches are
+    /// coded.
+    fn sample(len: usize, seed: u32) -> Vec<u8> {
+        let mut x = seed;
+        (0..len)
+            .map(|i| {
+                x = x.wrapping_mul(1664525).wrapping_add(1013904223);
+                if i / 64 % 2 == 0 {
+                    (x >> 24) as u8
+                } else {
+                    (i / 7) as u8
+                }
+            })
+            .collect()
+    }
+
+    fn compress(c: &mut SlideCompressor, input: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        c.encode_into(input, &mut out);
+        out
+    }
+
+    type State = (Vec<u8>, Vec<i32>, Vec<(i32, i32)>, i32);
+
+    fn state(c: &SlideCompressor) -> State {
+        let chains = c.chains.iter().map(|ch| (ch.prev, ch.next)).collect();
+        (c.text.clone(), c.map.clone(), chains, c.s)
+    }
+
+    #[test]
+    fn restore_reproduces_state() {
+        let first = sample(10000, 1);
+        let next = sample(10000, 3);
+        let mut reference = SlideCompressor::new();
+        compress(&mut reference, &first);
+        let stored = state(&reference);
+        let expected = compress(&mut reference, &next);
+        // Short enough to be journaled, then long enough to be copied.
+        for trial_len in [100, JOURNAL_LIMIT + 1000] {
+            let mut c = SlideCompressor::new();
+            compress(&mut c, &first);
+            c.store();
+            compress(&mut c, &sample(trial_len, 2));
+            c.restore();
+            assert!(state(&c) == stored, "state differs after {trial_len} bytes");
+            assert_eq!(compress(&mut c, &next), expected);
+        }
+    }
+
+    #[test]
+    fn efforts_decode_to_input() {
+        for input in [sample(1, 4), sample(300, 5), sample(50000, 6)] {
+            let mut sizes = Vec::new();
+            for effort in [LzssEffort::Fast, LzssEffort::Normal, LzssEffort::Optimal] {
+                let mut c = SlideCompressor::new();
+                c.set_effort(effort);
+                let compressed = compress(&mut c, &input);
+                let mut decoded = vec![0; input.len()];
+                let mut text = [0; SLIDE_N];
+                crate::tvpgl::tlg5_decompress_slide(
+                    &mut decoded,
+                    &compressed,
+                    compressed.len(),
+                    &mut text,
+                    0,
+                )
+                .unwrap();
+                assert!(decoded == input, "{effort:?} output differs");
+                sizes.push(compressed.len());
+            }
+            let (greedy_len, optimal_len) = (sizes[1], sizes[2]);
+            assert!(optimal_len <= greedy_len, "{optimal_len} > {greedy_len}");
+        }
     }
 }
@@ -1,22 +1,150 @@
-use std::io::Read;
+use crate::*;
 #[cfg(feature = "encode")]
 use std::io::Write;
+#[cfg(feature = "std")]
+use std::io::{Read, Seek, SeekFrom};
+
+/// Seekable input of the decoder.
+///
+/// Implemented for every `Read + Seek` type with the `std` feature, and for
+/// [`SliceReader`].
+pub trait Source {
+    /// Fill `buf`, failing at the end of the input.
+    fn read_fully(&mut self, buf: &mut [u8]) -> Result<()>;
+    /// Read up to `buf.len()` bytes, returning the number of bytes read.
+    fn read_some(&mut self, buf: &mut [u8]) -> Result<usize>;
+    /// Read exactly `len` bytes into `buf`, replacing its content.
+    ///
+    /// `buf` only grows as data is read, so a bogus length from a corrupt
+    /// file fails with an EOF error instead of allocating `len` bytes first.
+    fn read_exact_vec(&mut self, buf: &mut Vec<u8>, len: usize) -> Result<()>;
+    fn seek_to(&mut self, pos: u64) -> Result<()>;
+    fn position(&mut self) -> Result<u64>;
+    /// Total length of the input in bytes.
+    fn total_len(&mut self) -> Result<u64>;
+}
+
+/// Report a truncated stream as [`TlgError::UnexpectedEof`], the same as a
+/// truncated slice.
+#[cfg(feature = "std")]
+fn map_eof(err: std::io::Error) -> TlgError {
+    if err.kind() == std::io::ErrorKind::UnexpectedEof {
+        TlgError::UnexpectedEof
+    } else {
+        err.into()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read + Seek> Source for R {
+    fn read_fully(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.read_exact(buf).map_err(map_eof)
+    }
+
+    fn read_some(&mut self, buf: &mut [u8]) -> Result<usize> {
+        Ok(self.read(buf)?)
+    }
+
+    fn read_exact_vec(&mut self, buf: &mut Vec<u8>, len: usize) -> Result<()> {
+        buf.clear();
+        self.by_ref()
+            .take(len as u64)
+            .read_to_end(buf)
+            .map_err(map_eof)?;
+        if buf.len() != len {
+            return Err(TlgError::UnexpectedEof);
+        }
+        Ok(())
+    }
+
+    fn seek_to(&mut self, pos: u64) -> Result<()> {
+        self.seek(SeekFrom::Start(pos))?;
+        Ok(())
+    }
+
+    fn position(&mut self) -> Result<u64> {
+        Ok(self.stream_position()?)
+    }
+
+    fn total_len(&mut self) -> Result<u64> {
+        let pos = self.stream_position()?;
+        let len = self.seek(SeekFrom::End(0))?;
+        self.seek(SeekFrom::Start(pos))?;
+        Ok(len)
+    }
+}
+
+/// Reader over an in-memory TLG file.
+pub struct SliceReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> SliceReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn remaining(&self) -> &'a [u8] {
+        self.data.get(self.pos..).unwrap_or_default()
+    }
+}
+
+impl Source for SliceReader<'_> {
+    fn read_fully(&mut self, buf: &mut [u8]) -> Result<()> {
+        let src = self
+            .remaining()
+            .get(..buf.len())
+            .ok_or(TlgError::UnexpectedEof)?;
+        buf.copy_from_slice(src);
+        self.pos += buf.len();
+        Ok(())
+    }
+
+    fn read_some(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = buf.len().min(self.remaining().len());
+        self.read_fully(&mut buf[..n])?;
+        Ok(n)
+    }
+
+    fn read_exact_vec(&mut self, buf: &mut Vec<u8>, len: usize) -> Result<()> {
+        let src = self.remaining().get(..len).ok_or(TlgError::UnexpectedEof)?;
+        buf.clear();
+        buf.extend_from_slice(src);
+        self.pos += len;
+        Ok(())
+    }
+
+    fn seek_to(&mut self, pos: u64) -> Result<()> {
+        // Positions past the end are kept and fail on the next read.
+        self.pos = usize::try_from(pos).unwrap_or(usize::MAX);
+        Ok(())
+    }
+
+    fn position(&mut self) -> Result<u64> {
+        Ok(self.pos as u64)
+    }
+
+    fn total_len(&mut self) -> Result<u64> {
+        Ok(self.data.len() as u64)
+    }
+}
 
 pub trait ReadExt {
-    fn read_u32(&mut self) -> std::io::Result<u32>;
-    fn read_u8(&mut self) -> std::io::Result<u8>;
+    fn read_u32(&mut self) -> Result<u32>;
+    fn read_u8(&mut self) -> Result<u8>;
 }
 
-impl<R: Read> ReadExt for R {
-    fn read_u32(&mut self) -> std::io::Result<u32> {
+impl<S: Source + ?Sized> ReadExt for S {
+    fn read_u32(&mut self) -> Result<u32> {
         let mut buf = [0; 4];
-        self.read_exact(&mut buf)?;
+        self.read_fully(&mut buf)?;
         Ok(u32::from_le_bytes(buf))
     }
 
-    fn read_u8(&mut self) -> std::io::Result<u8> {
+    fn read_u8(&mut self) -> Result<u8> {
         let mut buf = [0; 1];
-        self.read_exact(&mut buf)?;
+        self.read_fully(&mut buf)?;
         Ok(buf[0])
     }
 }
@@ -0,0 +1,263 @@
+//! Ordered storage of the tags of a TLG0.0 SDS file.
+use alloc::vec::Vec;
+
+/// Tags of a TLG image, kept in insertion order.
+///
+/// The same key may appear more than once. Map-like accessors act on the
+/// first entry with a given key, so files are written back with their tags
+/// in the original order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct TlgTags {
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl TlgTags {
+    /// Create an empty tag list.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of entries, including duplicate keys.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if there are no tags.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Remove all tags.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Value of the first entry with the given key.
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_slice())
+    }
+
+    /// Mutable value of the first entry with the given key.
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut Vec<u8>> {
+        self.entries
+            .iter_mut()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    /// Values of all entries with the given key, in order.
+    pub fn get_all<'a>(&'a self, key: &'a [u8]) -> impl Iterator<Item = &'a [u8]> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(k, _)| k == key)
+            .map(|(_, v)| v.as_slice())
+    }
+
+    /// Returns `true` if an entry has the given key.
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Set the value of a key.
+    ///
+    /// The first entry with the key keeps its position and gets the new
+    /// value, and any later duplicates are removed. If the key is not
+    /// present, the entry is appended. Returns the previous value of the
+    /// first entry.
+    pub fn insert(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        value: impl Into<Vec<u8>>,
+    ) -> Option<Vec<u8>> {
+        let key = key.into();
+        let value = value.into();
+        match self.entries.iter().position(|(k, _)| *k == key) {
+            Some(i) => {
+                let old = core::mem::replace(&mut self.entries[i].1, value);
+                let rest = self.entries.split_off(i + 1);
+                self.entries
+                    .extend(rest.into_iter().filter(|(k, _)| *k != key));
+                Some(old)
+            }
+            None => {
+                self.entries.push((key, value));
+                None
+            }
+        }
+    }
+
+    /// Append an entry, even if the key is already present.
+    pub fn append(&mut self, key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) {
+        self.entries.push((key.into(), value.into()));
+    }
+
+    /// Remove all entries with the given key, returning the value of the
+    /// first one.
+    pub fn remove(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        let i = self.entries.iter().position(|(k, _)| k == key)?;
+        let (_, value) = self.entries.remove(i);
+        self.entries.retain(|(k, _)| k != key);
+        Some(value)
+    }
+
+    /// Iterate over the entries in order.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            inner: self.entries.iter(),
+        }
+    }
+
+    /// Iterate over the keys in order, including duplicates.
+    pub fn keys(&self) -> impl Iterator<Item = &[u8]> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    /// Iterate over the values in order.
+    pub fn values(&self) -> impl Iterator<Item = &[u8]> {
+        self.iter().map(|(_, v)| v)
+    }
+}
+
+/// Iterator over the entries of [`TlgTags`].
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    inner: core::slice::Iter<'a, (Vec<u8>, Vec<u8>)>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a [u8], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(k, v)| (k.as_slice(), v.as_slice()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for Iter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next_back()
+            .map(|(k, v)| (k.as_slice(), v.as_slice()))
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+impl<'a> IntoIterator for &'a TlgTags {
+    type Item = (&'a [u8], &'a [u8]);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl IntoIterator for TlgTags {
+    type Item = (Vec<u8>, Vec<u8>);
+    type IntoIter = alloc::vec::IntoIter<(Vec<u8>, Vec<u8>)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<K: Into<Vec<u8>>, V: Into<Vec<u8>>> FromIterator<(K, V)> for TlgTags {
+    /// Collect entries in order, keeping duplicate keys.
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut tags = Self::new();
+        tags.extend(iter);
+        tags
+    }
+}
+
+impl<K: Into<Vec<u8>>, V: Into<Vec<u8>>> Extend<(K, V)> for TlgTags {
+    /// Append entries in order, keeping duplicate keys.
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (k, v) in iter {
+            self.append(k, v);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags() -> TlgTags {
+        [
+            ("mode", "alpha"),
+            ("offs_x", "3"),
+            ("mode", "add"),
+            ("offs_y", "4"),
+            ("mode", "sub"),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    #[test]
+    fn insert_keeps_first_entry() {
+        let mut tags = tags();
+        assert_eq!(tags.insert("mode", "mul"), Some(b"alpha".to_vec()));
+        let entries: Vec<_> = tags.iter().collect();
+        assert_eq!(
+            entries,
+            [
+                (&b"mode"[..], &b"mul"[..]),
+                (b"offs_x", b"3"),
+                (b"offs_y", b"4"),
+            ]
+        );
+        assert_eq!(tags.insert("opacity", "128"), None);
+        assert_eq!(tags.keys().last(), Some(&b"opacity"[..]));
+    }
+
+    #[test]
+    fn remove_drops_every_entry() {
+        let mut tags = tags();
+        assert_eq!(tags.remove(b"mode"), Some(b"alpha".to_vec()));
+        assert!(!tags.contains_key(b"mode"));
+        assert_eq!(tags.keys().collect::<Vec<_>>(), [b"offs_x", b"offs_y"]);
+        assert_eq!(tags.remove(b"mode"), None);
+    }
+
+    #[test]
+    fn get_all_is_ordered() {
+        let tags = tags();
+        assert_eq!(tags.get(b"mode"), Some(&b"alpha"[..]));
+        assert_eq!(
+            tags.get_all(b"mode").collect::<Vec<_>>(),
+            [&b"alpha"[..], b"add", b"sub"]
+        );
+        assert_eq!(tags.get_all(b"opacity").count(), 0);
+    }
+
+    #[cfg(feature = "encode")]
+    #[test]
+    fn duplicates_are_saved_in_order() {
+        use crate::{DecodeOptions, Tlg, TlgColorType, load_tlg_from_slice, save_tlg};
+
+        let img = Tlg {
+            tags: tags(),
+            chunks: Vec::new(),
+            version: 5,
+            width: 1,
+            height: 1,
+            color: TlgColorType::Grayscale8,
+            data: alloc::vec![0],
+            tlg5_layout: None,
+        };
+        let mut data = Vec::new();
+        save_tlg(&img, &mut data).unwrap();
+        let chunk = b"4:mode=5:alpha,6:offs_x=1:3,4:mode=3:add,6:offs_y=1:4,4:mode=3:sub,";
+        assert!(data.windows(chunk.len()).any(|w| w == chunk));
+        let loaded = load_tlg_from_slice(&data, &DecodeOptions::default()).unwrap();
+        assert_eq!(loaded.tags, img.tags);
+    }
+}
@@ -2,106 +2,186 @@ use super::*;
 use crate::slide::*;
 use crate::stream::*;
 use overf::wrapping;
-use std::io::{Seek, Write};
+use std::io::Write;
 
-const BLOCK_HEIGHT: usize = 4;
+/// TLG5 stream whose block size table is computed, ready to be written.
+///
+/// The table precedes the blocks, so the image is compressed twice: once to
+/// compute the size of each block and once to write it. Only one block is
+/// kept in memory.
+pub struct Tlg5Stream<'a> {
+    tlg: &'a Tlg,
+    options: &'a Tlg5EncodeOptions,
+    colors: u8,
+    block_height: u32,
+    block_rows: usize,
+    blocksizes: Vec<u32>,
+}
 
-pub fn save_tlg5<W: Write + Seek>(tlg: &Tlg, writer: &mut W) -> Result<()> {
-    writer.write_all(b"TLG5.0\x00raw\x1a")?;
-    let colors = match tlg.color {
-        TlgColorType::Bgra32 => 4,
-        TlgColorType::Bgr24 => 3,
-        TlgColorType::Grayscale8 => 1,
-    };
-    writer.write_u8(colors)?;
-    writer.write_u32(tlg.width)?;
-    writer.write_u32(tlg.height)?;
-    writer.write_u32(BLOCK_HEIGHT as u32)?;
-    let blockcount = ((tlg.height as usize - 1) / BLOCK_HEIGHT) + 1;
-    let mut compressor = SlideCompressor::new();
-    let mut written = [0; 4];
-    let mut blocksizes = vec![0; blockcount];
-    let mut cmpinbuf = vec![vec![0u8; tlg.width as usize * BLOCK_HEIGHT]; colors as usize];
-    let blocksizepos = writer.stream_position()?;
-    for _ in 0..blockcount {
-        writer.write_all(b"    ")?; // Place holders
+impl<'a> Tlg5Stream<'a> {
+    /// Check the options and compute the block size table.
+    pub fn new(tlg: &'a Tlg, options: &'a Tlg5EncodeOptions) -> Result<Self> {
+        let colors = match tlg.color {
+            TlgColorType::Bgra32 => 4,
+            TlgColorType::Bgr24 => 3,
+            TlgColorType::Grayscale8 => 1,
+        };
+        // Reproduce the original stream if its layout was recorded.
+        let layout = tlg.tlg5_layout.as_ref();
+        let block_height = layout.map_or(options.block_height, |l| l.block_height);
+        if block_height == 0 {
+            return Err(TlgError::EncodeError(
+                "TLG5 block height must be non-zero".to_string(),
+            ));
+        }
+        let block_rows = block_height.min(tlg.height) as usize;
+        let blockcount = ((tlg.height as usize - 1) / block_rows) + 1;
+        if let Some(layout) = layout
+            && layout.raw.len() != blockcount * colors as usize
+        {
+            return Err(TlgError::EncodeError(format!(
+                "TLG5 layout has {} channels, expected {}",
+                layout.raw.len(),
+                blockcount * colors as usize
+            )));
+        }
+        let mut stream = Self {
+            tlg,
+            options,
+            colors,
+            block_height,
+            block_rows,
+            blocksizes: Vec::new(),
+        };
+        let mut blocksizes = vec![0u32; blockcount];
+        stream.encode_blocks(|block, header, data| {
+            blocksizes[block] =
+                u32::try_from(blocksizes[block] as usize + header.len() + data.len())
+                    .map_err(|_| TlgError::EncodeError("TLG5 block is too large".to_string()))?;
+            Ok(())
+        })?;
+        stream.blocksizes = blocksizes;
+        Ok(stream)
     }
-    let mut block = 0;
-    for blk_y in (0..tlg.height as usize).step_by(BLOCK_HEIGHT) {
-        let ylim = (blk_y + BLOCK_HEIGHT).min(tlg.height as usize);
-        let mut inp = 0;
-        for y in blk_y..ylim {
-            let upper = if y != 0 {
-                &tlg.data[(y - 1) * tlg.width as usize * colors as usize
-                    ..y * tlg.width as usize * colors as usize]
-            } else {
-                &[]
-            };
-            let mut upper_pos = 0;
-            let current = &tlg.data[y * tlg.width as usize * colors as usize
-                ..(y + 1) * tlg.width as usize * colors as usize];
-            let mut current_pos = 0;
-            let mut prevcl = [0; 4];
-            let mut val = [0; 4];
-            for _ in 0..tlg.width as usize {
-                for c in 0..colors as usize {
-                    let cl = if y != 0 {
-                        let c = current[current_pos];
-                        current_pos += 1;
-                        let p = upper[upper_pos];
-                        upper_pos += 1;
-                        wrapping! { c - p }
-                    } else {
-                        let c = current[current_pos];
-                        current_pos += 1;
-                        c
-                    } as i32;
-                    val[c] = wrapping! { cl - prevcl[c] };
-                    prevcl[c] = cl;
-                }
-                if colors == 1 {
-                    cmpinbuf[0][inp] = val[0] as u8;
-                } else if colors == 3 {
-                    cmpinbuf[0][inp] = wrapping! { val[0] - val[1] } as u8;
-                    cmpinbuf[1][inp] = val[1] as u8;
-                    cmpinbuf[2][inp] = wrapping! { val[2] - val[1] } as u8;
-                } else if colors == 4 {
-                    cmpinbuf[0][inp] = wrapping! { val[0] - val[1] } as u8;
-                    cmpinbuf[1][inp] = val[1] as u8;
-                    cmpinbuf[2][inp] = wrapping! { val[2] - val[1] } as u8;
-                    cmpinbuf[3][inp] = val[3] as u8;
+
+    /// Size of the stream in bytes.
+    pub fn size(&self) -> usize {
+        11 + 13
+            + self.blocksizes.len() * 4
+            + self.blocksizes.iter().map(|&s| s as usize).sum::<usize>()
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(b"TLG5.0\x00raw\x1a")?;
+        writer.write_u8(self.colors)?;
+        writer.write_u32(self.tlg.width)?;
+        writer.write_u32(self.tlg.height)?;
+        writer.write_u32(self.block_height)?;
+        for &blocksize in &self.blocksizes {
+            writer.write_u32(blocksize)?;
+        }
+        self.encode_blocks(|_, header, data| {
+            writer.write_all(header)?;
+            writer.write_all(data)?;
+            Ok(())
+        })
+    }
+
+    /// Compress the blocks in order, passing the index of the block, the
+    /// header and the stored data of each channel to `f`.
+    fn encode_blocks<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(usize, &[u8; 5], &[u8]) -> Result<()>,
+    {
+        let tlg = self.tlg;
+        let options = self.options;
+        let colors = self.colors;
+        let block_rows = self.block_rows;
+        let layout = tlg.tlg5_layout.as_ref();
+        let mut compressor = SlideCompressor::new();
+        // A recorded layout is reproduced with the matches the original
+        // encoder finds.
+        compressor.set_effort(match layout {
+            Some(_) => LzssEffort::Normal,
+            None => options.effort,
+        });
+        let mut cmpinbuf = vec![vec![0u8; tlg.width as usize * block_rows]; colors as usize];
+        let mut outbuf = Vec::new();
+        for (block, blk_y) in (0..tlg.height as usize).step_by(block_rows).enumerate() {
+            let ylim = (blk_y + block_rows).min(tlg.height as usize);
+            let mut inp = 0;
+            for y in blk_y..ylim {
+                let upper = if y != 0 {
+                    &tlg.data[(y - 1) * tlg.width as usize * colors as usize
+                        ..y * tlg.width as usize * colors as usize]
+                } else {
+                    &[]
+                };
+                let mut upper_pos = 0;
+                let current = &tlg.data[y * tlg.width as usize * colors as usize
+                    ..(y + 1) * tlg.width as usize * colors as usize];
+                let mut current_pos = 0;
+                let mut prevcl = [0; 4];
+                let mut val = [0; 4];
+                for _ in 0..tlg.width as usize {
+                    for c in 0..colors as usize {
+                        let cl = if y != 0 {
+                            let c = current[current_pos];
+                            current_pos += 1;
+                            let p = upper[upper_pos];
+                            upper_pos += 1;
+                            wrapping! { c - p }
+                        } else {
+                            let c = current[current_pos];
+                            current_pos += 1;
+                            c
+                        } as i32;
+                        val[c] = wrapping! { cl - prevcl[c] };
+                        prevcl[c] = cl;
+                    }
+                    if colors == 1 {
+                        cmpinbuf[0][inp] = val[0] as u8;
+                    } else if colors == 3 {
+                        cmpinbuf[0][inp] = wrapping! { val[0] - val[1] } as u8;
+                        cmpinbuf[1][inp] = val[1] as u8;
+                        cmpinbuf[2][inp] = wrapping! { val[2] - val[1] } as u8;
+                    } else if colors == 4 {
+                        cmpinbuf[0][inp] = wrapping! { val[0] - val[1] } as u8;
+                        cmpinbuf[1][inp] = val[1] as u8;
+                        cmpinbuf[2][inp] = wrapping! { val[2] - val[1] } as u8;
+                        cmpinbuf[3][inp] = val[3] as u8;
+                    }
+                    inp += 1;
                 }
-                inp += 1;
             }
-        }
-        // LZSS
-        let mut blocksize = 0;
-        for c in 0..colors as usize {
-            compressor.store();
-            let mut outbuf = Vec::new();
-            let wrote = compressor.encode_into(&cmpinbuf[c][..inp], &mut outbuf);
-            if wrote < inp {
-                writer.write_u8(0)?;
-                writer.write_u32(wrote as u32)?;
-                writer.write_all(&outbuf)?;
-                blocksize += wrote + 4 + 1;
-            } else {
-                compressor.restore();
-                writer.write_u8(1)?;
-                writer.write_u32(inp as u32)?;
-                writer.write_all(&cmpinbuf[c][..inp])?;
-                blocksize += inp + 4 + 1;
+            // LZSS
+            for (c, cmpin) in cmpinbuf.iter().enumerate() {
+                let raw = match layout {
+                    Some(l) => Some(l.raw[block * colors as usize + c]),
+                    None => options.force_raw.then_some(true),
+                };
+                outbuf.clear();
+                let compressed = raw != Some(true) && {
+                    compressor.store();
+                    let wrote = compressor.encode_into(&cmpin[..inp], &mut outbuf);
+                    // Decoders only have room for a block and 10 more bytes.
+                    if wrote < inp || (raw == Some(false) && wrote <= cmpin.len() + 10) {
+                        true
+                    } else {
+                        compressor.restore();
+                        false
+                    }
+                };
+                let data = if compressed {
+                    &outbuf[..]
+                } else {
+                    &cmpin[..inp]
+                };
+                let mut header = [!compressed as u8, 0, 0, 0, 0];
+                header[1..].copy_from_slice(&(data.len() as u32).to_le_bytes());
+                f(block, &header, data)?;
             }
-            written[c] += wrote;
         }
-        blocksizes[block] = blocksize;
-        block += 1;
-    }
-    let pos_save = writer.stream_position()?;
-    writer.seek(std::io::SeekFrom::Start(blocksizepos))?;
-    for i in 0..blockcount {
-        writer.write_u32(blocksizes[i] as u32)?;
+        Ok(())
     }
-    writer.seek(std::io::SeekFrom::Start(pos_save))?;
-    Ok(())
 }
@@ -0,0 +1,424 @@
+use super::*;
+use crate::slide::*;
+use crate::stream::*;
+use crate::tvpgl::*;
+use overf::wrapping;
+use std::io::Write;
+
+/// Bytes of zero bits after which a golomb value is stored as a raw byte.
+const GOLOMB_GIVE_UP_BYTES: usize = 4;
+/// Number of color filters tried for each block.
+const FILTER_TRY_COUNT: u8 = 16;
+
+struct Tlg6BitStream {
+    buf: Vec<u8>,
+    bit_pos: u32,
+}
+
+impl Tlg6BitStream {
+    fn new() -> Self {
+        Self {
+            buf: vec![0],
+            bit_pos: 0,
+        }
+    }
+
+    fn byte_pos(&self) -> usize {
+        self.buf.len() - 1
+    }
+
+    fn bit_length(&self) -> usize {
+        self.byte_pos() * 8 + self.bit_pos as usize
+    }
+
+    fn put_1bit(&mut self, b: bool) {
+        if b {
+            *self.buf.last_mut().unwrap() |= 1 << self.bit_pos;
+        }
+        self.bit_pos += 1;
+        if self.bit_pos == 8 {
+            self.bit_pos = 0;
+            self.buf.push(0);
+        }
+    }
+
+    /// Put a gamma code. `v` must be larger than 0.
+    fn put_gamma(&mut self, mut v: usize) {
+        let mut t = v >> 1;
+        let mut cnt = 0;
+        while t != 0 {
+            self.put_1bit(false);
+            t >>= 1;
+            cnt += 1;
+        }
+        self.put_1bit(true);
+        for _ in 0..cnt {
+            self.put_1bit(v & 1 != 0);
+            v >>= 1;
+        }
+    }
+
+    /// Put the lowest `len` bits of `v`.
+    fn put_value(&mut self, mut v: u32, len: u32) {
+        for _ in 0..len {
+            self.put_1bit(v & 1 != 0);
+            v >>= 1;
+        }
+    }
+
+    /// Move all written bytes into `out` and reset the stream.
+    fn flush_into(&mut self, out: &mut Vec<u8>) {
+        let len = if self.bit_pos != 0 {
+            self.buf.len()
+        } else {
+            self.byte_pos()
+        };
+        out.extend_from_slice(&self.buf[..len]);
+        self.buf.clear();
+        self.buf.push(0);
+        self.bit_pos = 0;
+    }
+}
+
+fn gamma_bit_length(v: usize) -> usize {
+    (v.ilog2() as usize) * 2 + 1
+}
+
+/// Map a non-zero signed residual to the value stored by the golomb coder.
+fn golomb_m(v: u8) -> u32 {
+    let e = v as i8 as i32;
+    (if e >= 0 { 2 * e } else { -2 * e - 1 } - 1) as u32
+}
+
+fn compress_values_golomb(bs: &mut Tlg6BitStream, buf: &[u8]) {
+    bs.put_value((buf[0] != 0) as u32, 1);
+    let mut n = TLG6_GOLOMB_N_COUNT - 1;
+    let mut a = 0usize;
+    let mut i = 0;
+    while i < buf.len() {
+        let start = i;
+        while i < buf.len() && buf[i] == 0 {
+            i += 1;
+        }
+        if i > start {
+            bs.put_gamma(i - start);
+        }
+        if i == buf.len() {
+            break;
+        }
+        let start = i;
+        while i < buf.len() && buf[i] != 0 {
+            i += 1;
+        }
+        bs.put_gamma(i - start);
+        for &v in &buf[start..i] {
+            let k = TLG6_GOLOMB_BIT_LENGTH_TABLE[a][n] as u32;
+            let m = golomb_m(v);
+            let q = m >> k;
+            let store_limit = bs.byte_pos() + GOLOMB_GIVE_UP_BYTES;
+            let mut gave_up = false;
+            for _ in 0..q {
+                if bs.byte_pos() == store_limit {
+                    gave_up = true;
+                    break;
+                }
+                bs.put_1bit(false);
+            }
+            if gave_up || bs.byte_pos() == store_limit {
+                bs.put_value(q, 8);
+            } else {
+                bs.put_1bit(true);
+            }
+            bs.put_value(m, k);
+            a += (m >> 1) as usize;
+            if n == 0 {
+                n = TLG6_GOLOMB_N_COUNT - 1;
+                a >>= 1;
+            } else {
+                n -= 1;
+            }
+        }
+    }
+}
+
+/// Estimate the number of bits [`compress_values_golomb`] needs for `buf`.
+fn golomb_bit_length(buf: &[u8]) -> usize {
+    let mut bits = 1;
+    let mut n = TLG6_GOLOMB_N_COUNT - 1;
+    let mut a = 0usize;
+    let mut i = 0;
+    while i < buf.len() {
+        let start = i;
+        while i < buf.len() && buf[i] == 0 {
+            i += 1;
+        }
+        if i > start {
+            bits += gamma_bit_length(i - start);
+        }
+        if i == buf.len() {
+            break;
+        }
+        let start = i;
+        while i < buf.len() && buf[i] != 0 {
+            i += 1;
+        }
+        bits += gamma_bit_length(i - start);
+        for &v in &buf[start..i] {
+            let k = TLG6_GOLOMB_BIT_LENGTH_TABLE[a][n] as u32;
+            let m = golomb_m(v);
+            bits += (m >> k) as usize + 1 + k as usize;
+            a += (m >> 1) as usize;
+            if n == 0 {
+                n = TLG6_GOLOMB_N_COUNT - 1;
+                a >>= 1;
+            } else {
+                n -= 1;
+            }
+        }
+    }
+    bits
+}
+
+/// Forward color transform, the inverse of the one applied by `tlg6_decode_line`.
+fn apply_color_filter(b: &mut [u8], g: &mut [u8], r: &mut [u8], code: u8) {
+    for ((b, g), r) in b.iter_mut().zip(g.iter_mut()).zip(r.iter_mut()) {
+        wrapping! {
+            match code {
+                1 => {
+                    *b -= *g;
+                    *r -= *g;
+                }
+                2 => {
+                    *r -= *g;
+                    *g -= *b;
+                }
+                3 => {
+                    *b -= *g;
+                    *g -= *r;
+                }
+                4 => {
+                    *r -= *g;
+                    *g -= *b;
+                    *b -= *r;
+                }
+                5 => {
+                    *g -= *b;
+                    *b -= *r;
+                }
+                6 => *b -= *g,
+                7 => *g -= *b,
+                8 => *r -= *g,
+                9 => {
+                    *b -= *g;
+                    *g -= *r;
+                    *r -= *b;
+                }
+                10 => {
+                    *b -= *r;
+                    *g -= *r;
+                }
+                11 => {
+                    *g -= *b;
+                    *r -= *b;
+                }
+                12 => {
+                    *g -= *r;
+                    *r -= *b;
+                }
+                13 => {
+                    *g -= *r;
+                    *r -= *b;
+                    *b -= *g;
+                }
+                14 => {
+                    *r -= *b;
+                    *b -= *g;
+                    *g -= *r;
+                }
+                15 => {
+                    *g -= *b << 1;
+                    *r -= *b << 1;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Find the color filter giving the smallest estimated golomb output.
+///
+/// Returns the filter code and the estimated size in bits.
+fn detect_color_filter(b: &[u8], g: &[u8], r: &[u8]) -> (u8, usize) {
+    let len = b.len();
+    let mut best = (0, usize::MAX);
+    let mut bbuf = [0u8; TLG6_W_BLOCK_SIZE * TLG6_H_BLOCK_SIZE];
+    let mut gbuf = [0u8; TLG6_W_BLOCK_SIZE * TLG6_H_BLOCK_SIZE];
+    let mut rbuf = [0u8; TLG6_W_BLOCK_SIZE * TLG6_H_BLOCK_SIZE];
+    for code in 0..FILTER_TRY_COUNT {
+        bbuf[..len].copy_from_slice(b);
+        gbuf[..len].copy_from_slice(g);
+        rbuf[..len].copy_from_slice(r);
+        apply_color_filter(&mut bbuf[..len], &mut gbuf[..len], &mut rbuf[..len], code);
+        let size = golomb_bit_length(&bbuf[..len])
+            + golomb_bit_length(&gbuf[..len])
+            + golomb_bit_length(&rbuf[..len]);
+        if size < best.1 {
+            best = (code, size);
+        }
+    }
+    best
+}
+
+/// TLG6 stream, encoded in memory because the largest bit length of the
+/// stripes precedes them.
+pub struct Tlg6Stream<'a> {
+    tlg: &'a Tlg,
+    colors: u8,
+    max_bit_length: u32,
+    filter_buf: Vec<u8>,
+    stripes: Vec<u8>,
+}
+
+impl<'a> Tlg6Stream<'a> {
+    /// Size of the stream in bytes.
+    pub fn size(&self) -> usize {
+        // Mark, header with the largest bit length, then the filter types
+        // with their size and the stripes.
+        11 + 16 + 4 + self.filter_buf.len() + self.stripes.len()
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let tlg = self.tlg;
+        writer.write_all(b"TLG6.0\x00raw\x1a")?;
+        writer.write_u8(self.colors)?;
+        writer.write_u8(0)?; // data flags
+        writer.write_u8(0)?; // color types
+        writer.write_u8(0)?; // external golomb table
+        writer.write_u32(tlg.width)?;
+        writer.write_u32(tlg.height)?;
+        writer.write_u32(self.max_bit_length)?;
+        writer.write_u32(self.filter_buf.len() as u32)?;
+        writer.write_all(&self.filter_buf)?;
+        writer.write_all(&self.stripes)?;
+        Ok(())
+    }
+}
+
+pub fn encode_tlg6<'a>(tlg: &'a Tlg, options: &EncodeOptions) -> Result<Tlg6Stream<'a>> {
+    let colors: usize = match tlg.color {
+        TlgColorType::Bgra32 => 4,
+        TlgColorType::Bgr24 => 3,
+        TlgColorType::Grayscale8 => 1,
+    };
+    let width = tlg.width as usize;
+    let height = tlg.height as usize;
+    let stride = width * colors;
+    let x_block_count = (width - 1) / TLG6_W_BLOCK_SIZE + 1;
+    let y_block_count = (height - 1) / TLG6_H_BLOCK_SIZE + 1;
+    let mut filter_types = Vec::with_capacity(x_block_count * y_block_count);
+    let mut block_buf = vec![vec![0u8; TLG6_H_BLOCK_SIZE * width]; colors];
+    let mut buf = [[[0u8; TLG6_W_BLOCK_SIZE * TLG6_H_BLOCK_SIZE]; 4]; 2];
+    let mut bs = Tlg6BitStream::new();
+    let mut stripes = Vec::new();
+    let mut max_bit_length = 0;
+    let pixel = |x: usize, y: usize, c: usize| tlg.data[y * stride + x * colors + c];
+    for y in (0..height).step_by(TLG6_H_BLOCK_SIZE) {
+        let ylim = (y + TLG6_H_BLOCK_SIZE).min(height);
+        let mut gwp = 0;
+        for (xp, x) in (0..width).step_by(TLG6_W_BLOCK_SIZE).enumerate() {
+            let xlim = (x + TLG6_W_BLOCK_SIZE).min(width);
+            let bw = xlim - x;
+            let count = (ylim - y) * bw;
+            let mut best = (0, 0, usize::MAX);
+            for (p, buf) in buf.iter_mut().enumerate() {
+                // Residuals of MED (p = 0) or average (p = 1) prediction, in
+                // the order the decoder reads them: odd blocks are stored
+                // bottom-up and odd lines are stored right to left.
+                let mut wp = 0;
+                for i in 0..(ylim - y) {
+                    let yy = if xp & 1 == 0 { y + i } else { ylim - 1 - i };
+                    for j in 0..bw {
+                        let xx = if yy & 1 == 0 { x + j } else { xlim - 1 - j };
+                        for (c, buf) in buf.iter_mut().enumerate().take(colors) {
+                            let pa = if xx > 0 { pixel(xx - 1, yy, c) } else { 0 };
+                            let pb = if yy > 0 { pixel(xx, yy - 1, c) } else { 0 };
+                            let px = pixel(xx, yy, c);
+                            let py = if p == 0 {
+                                let pc = if xx > 0 && yy > 0 {
+                                    pixel(xx - 1, yy - 1, c)
+                                } else {
+                                    0
+                                };
+                                let min_a_b = pa.min(pb);
+                                let max_a_b = pa.max(pb);
+                                if pc >= max_a_b {
+                                    min_a_b
+                                } else if pc < min_a_b {
+                                    max_a_b
+                                } else {
+                                    wrapping! { pa + pb - pc }
+                                }
+                            } else {
+                                ((pa as u32 + pb as u32 + 1) >> 1) as u8
+                            };
+                            buf[wp] = wrapping! { px - py };
+                        }
+                        wp += 1;
+                    }
+                }
+                let (ft, mut size) = if colors >= 3 {
+                    detect_color_filter(&buf[0][..count], &buf[1][..count], &buf[2][..count])
+                } else {
+                    (0, golomb_bit_length(&buf[0][..count]))
+                };
+                if colors == 4 {
+                    size += golomb_bit_length(&buf[3][..count]);
+                }
+                if size < best.2 {
+                    best = (p, ft, size);
+                }
+            }
+            let (p, ft, _) = best;
+            for (c, block_buf) in block_buf.iter_mut().enumerate() {
+                block_buf[gwp..gwp + count].copy_from_slice(&buf[p][c][..count]);
+            }
+            if colors >= 3 {
+                let (b, rest) = block_buf.split_at_mut(1);
+                let (g, r) = rest.split_at_mut(1);
+                apply_color_filter(
+                    &mut b[0][gwp..gwp + count],
+                    &mut g[0][gwp..gwp + count],
+                    &mut r[0][gwp..gwp + count],
+                    ft,
+                );
+            }
+            filter_types.push((ft << 1) + p as u8);
+            gwp += count;
+        }
+        for block_buf in &block_buf {
+            compress_values_golomb(&mut bs, &block_buf[..gwp]);
+            let bit_length = bs.bit_length();
+            if bit_length & 0xc000_0000 != 0 {
+                return Err(TlgError::EncodeError(
+                    "Too large bit length (given image may be too large)".to_string(),
+                ));
+            }
+            max_bit_length = max_bit_length.max(bit_length);
+            // The two most significant bits select the entropy coding
+            // method; only golomb (0) is supported by the decoder.
+            stripes.write_u32(bit_length as u32)?;
+            bs.flush_into(&mut stripes);
+        }
+    }
+    let mut compressor = SlideCompressor::with_text(&tlg6_init_lzss_text());
+    compressor.set_effort(options.tlg6_filter_effort);
+    let mut filter_buf = Vec::new();
+    compressor.encode_into(&filter_types, &mut filter_buf);
+    Ok(Tlg6Stream {
+        tlg,
+        colors: colors as u8,
+        max_bit_length: max_bit_length as u32,
+        filter_buf,
+        stripes,
+    })
+}
@@ -1,7 +1,7 @@
 use crate::*;
 use overf::wrapping;
 
-const TLG6_GOLOMB_N_COUNT: usize = 4;
+pub const TLG6_GOLOMB_N_COUNT: usize = 4;
 const TLG6_LEADING_ZERO_TABLE_BITS: usize = 12;
 const TLG6_LEADING_ZERO_TABLE_SIZE: usize = 1 << TLG6_LEADING_ZERO_TABLE_BITS;
 const TLG6_GOLOMB_COMPRESSED: [[u16; 9]; TLG6_GOLOMB_N_COUNT] = [
@@ -14,16 +14,16 @@ const TLG6_GLOBMB_TABLE_SIZE: usize = TLG6_GOLOMB_N_COUNT * 2 * 128;
 pub const TLG6_W_BLOCK_SIZE: usize = 8;
 pub const TLG6_H_BLOCK_SIZE: usize = 8;
 
-lazy_static::lazy_static! {
-    static ref TLG6_LEADING_ZERO_TABLE: [u8; TLG6_LEADING_ZERO_TABLE_SIZE] =
-        tlg6_init_leading_zero_table();
-    static ref TLG6_GOLOMB_BIT_LENGTH_TABLE: [[i8; TLG6_GOLOMB_N_COUNT]; TLG6_GLOBMB_TABLE_SIZE] =
-        tlg6_init_golomb_table();
-}
+static TLG6_LEADING_ZERO_TABLE: [u8; TLG6_LEADING_ZERO_TABLE_SIZE] = tlg6_init_leading_zero_table();
+/// Golomb bit length for each accumulated magnitude and position in the
+/// 4-value adaptation cycle.
+pub static TLG6_GOLOMB_BIT_LENGTH_TABLE: [[i8; TLG6_GOLOMB_N_COUNT]; TLG6_GLOBMB_TABLE_SIZE] =
+    tlg6_init_golomb_table();
 
-fn tlg6_init_leading_zero_table() -> [u8; TLG6_LEADING_ZERO_TABLE_SIZE] {
+const fn tlg6_init_leading_zero_table() -> [u8; TLG6_LEADING_ZERO_TABLE_SIZE] {
     let mut table = [0; TLG6_LEADING_ZERO_TABLE_SIZE];
-    for i in 0..TLG6_LEADING_ZERO_TABLE_SIZE {
+    let mut i = 0;
+    while i < TLG6_LEADING_ZERO_TABLE_SIZE {
         let mut cnt = 0;
         let mut j = 1;
         while j != TLG6_LEADING_ZERO_TABLE_SIZE && i & j == 0 {
@@ -35,45 +35,65 @@ fn tlg6_init_leading_zero_table() -> [u8; TLG6_LEADING_ZERO_TABLE_SIZE] {
             cnt = 0;
         }
         table[i] = cnt as u8;
+        i += 1;
     }
     table
 }
 
-fn tlg6_init_golomb_table() -> [[i8; TLG6_GOLOMB_N_COUNT]; TLG6_GLOBMB_TABLE_SIZE] {
+const fn tlg6_init_golomb_table() -> [[i8; TLG6_GOLOMB_N_COUNT]; TLG6_GLOBMB_TABLE_SIZE] {
     let mut table = [[0; TLG6_GOLOMB_N_COUNT]; TLG6_GLOBMB_TABLE_SIZE];
-    for n in 0..TLG6_GOLOMB_N_COUNT {
+    let mut n = 0;
+    while n < TLG6_GOLOMB_N_COUNT {
         let mut a = 0;
-        for i in 0..9 {
-            for _ in 0..TLG6_GOLOMB_COMPRESSED[n][i] {
+        let mut i = 0;
+        while i < 9 {
+            let mut j = 0;
+            while j < TLG6_GOLOMB_COMPRESSED[n][i] {
                 table[a][n] = i as i8;
                 a += 1;
+                j += 1;
             }
+            i += 1;
         }
-        debug_assert!(a == TLG6_GLOBMB_TABLE_SIZE);
+        // Evaluated at compile time.
+        assert!(a == TLG6_GLOBMB_TABLE_SIZE);
+        n += 1;
     }
     table
 }
 
+/// Initial LZSS dictionary used for the TLG6 filter type table.
+pub fn tlg6_init_lzss_text() -> [u8; 4096] {
+    let mut text = [0u8; 4096];
+    let mut p = 0;
+    for i in 0..0x20u8 {
+        for j in 0..0x10u8 {
+            text[p..p + 4].fill(i);
+            text[p + 4..p + 8].fill(j);
+            p += 8;
+        }
+    }
+    text
+}
+
 pub fn tlg5_compose_colors1(outp: &mut [u8], upper: &[u8], buf: &[&[u8]], width: u32) {
-    let mut outpos = 0usize;
-    let mut upper_pos = 0usize;
     let mut pb = 0u8;
-    for x in 0..width as usize {
-        let b = buf[0][x];
+    for ((&b, out), &up) in buf[0][..width as usize].iter().zip(outp).zip(upper) {
         wrapping! { pb += b };
-        outp[outpos] = wrapping! { pb + upper[upper_pos]};
-        outpos += 1;
-        upper_pos += 1;
+        *out = wrapping! { pb + up };
     }
 }
 
 pub fn tlg5_compose_colors3(outp: &mut [u8], upper: &[u8], buf: &[&[u8]], width: u32) {
-    let mut outpos = 0usize;
-    let mut upper_pos = 0usize;
     let mut pr = 0u8;
     let mut pg = 0u8;
     let mut pb = 0u8;
-    for x in 0..width as usize {
+    for (x, (out, up)) in outp
+        .chunks_exact_mut(3)
+        .zip(upper.chunks_exact(3))
+        .take(width as usize)
+        .enumerate()
+    {
         let mut b = buf[0][x];
         let g = buf[1][x];
         let mut r = buf[2][x];
@@ -86,26 +106,23 @@ pub fn tlg5_compose_colors3(outp: &mut [u8], upper: &[u8], buf: &[&[u8]], width:
             pg += g;
             pr += r;
         }
-        outp[outpos] = wrapping! { pb + upper[upper_pos]};
-        outpos += 1;
-        upper_pos += 1;
-        outp[outpos] = wrapping! { pg + upper[upper_pos]};
-        outpos += 1;
-        upper_pos += 1;
-        outp[outpos] = wrapping! { pr + upper[upper_pos]};
-        outpos += 1;
-        upper_pos += 1;
+        out[0] = wrapping! { pb + up[0] };
+        out[1] = wrapping! { pg + up[1] };
+        out[2] = wrapping! { pr + up[2] };
     }
 }
 
 pub fn tlg5_compose_colors4(outp: &mut [u8], upper: &[u8], buf: &[&[u8]], width: u32) {
-    let mut outpos = 0usize;
-    let mut upper_pos = 0usize;
     let mut pr = 0u8;
     let mut pg = 0u8;
     let mut pb = 0u8;
     let mut pa = 0u8;
-    for x in 0..width as usize {
+    for (x, (out, up)) in outp
+        .chunks_exact_mut(4)
+        .zip(upper.chunks_exact(4))
+        .take(width as usize)
+        .enumerate()
+    {
         let mut b = buf[0][x];
         let g = buf[1][x];
         let mut r = buf[2][x];
@@ -120,18 +137,10 @@ pub fn tlg5_compose_colors4(outp: &mut [u8], upper: &[u8], buf: &[&[u8]], width:
             pr += r;
             pa += a;
         }
-        outp[outpos] = wrapping! { pb + upper[upper_pos]};
-        outpos += 1;
-        upper_pos += 1;
-        outp[outpos] = wrapping! { pg + upper[upper_pos]};
-        outpos += 1;
-        upper_pos += 1;
-        outp[outpos] = wrapping! { pr + upper[upper_pos]};
-        outpos += 1;
-        upper_pos += 1;
-        outp[outpos] = wrapping! { pa + upper[upper_pos]};
-        outpos += 1;
-        upper_pos += 1;
+        out[0] = wrapping! { pb + up[0] };
+        out[1] = wrapping! { pg + up[1] };
+        out[2] = wrapping! { pr + up[2] };
+        out[3] = wrapping! { pa + up[3] };
     }
 }
 
@@ -141,51 +150,101 @@ pub fn tlg5_decompress_slide(
     insize: usize,
     text: &mut [u8],
     mut r: usize,
-) -> usize {
+) -> Result<usize> {
+    let inp = inp.get(..insize).ok_or(TlgError::IndexOutOfRange)?;
+    let text: &mut [u8; 4096] = text
+        .get_mut(..4096)
+        .and_then(|t| t.try_into().ok())
+        .ok_or(TlgError::IndexOutOfRange)?;
+    let byte = |pos: usize| inp.get(pos).copied().ok_or(TlgError::IndexOutOfRange);
     let mut flags = 0u32;
     let mut inpos = 0usize;
     let mut outpos = 0usize;
+    r &= 4095;
     while inpos < insize {
         wrapping! { flags >>= 1 };
         if flags & 256 == 0 {
-            flags = inp[inpos] as u32 | 0xff00;
+            flags = byte(inpos)? as u32 | 0xff00;
             inpos += 1;
         }
         if flags & 1 != 0 {
-            let mut mpos =
-                wrapping! { inp[inpos] as usize | ((inp[inpos + 1] as usize & 0xf) << 8) };
-            let mut mlen = wrapping! { (inp[inpos + 1] as usize & 0xf0) >> 4 };
+            let lo = byte(inpos)? as usize;
+            let hi = byte(inpos + 1)? as usize;
+            let mut mpos = lo | ((hi & 0xf) << 8);
+            let mut mlen = ((hi & 0xf0) >> 4) + 3;
             inpos += 2;
-            mlen += 3;
             if mlen == 18 {
-                mlen += inp[inpos] as usize;
+                mlen += byte(inpos)? as usize;
                 inpos += 1;
             }
-            while mlen > 0 {
-                out[outpos] = text[mpos];
-                outpos += 1;
-                text[r] = text[mpos];
-                r += 1;
-                mpos += 1;
-                wrapping! {
-                    mpos &= 4095;
-                    r &= 4095;
-                }
-                mlen -= 1;
+            let dest = out
+                .get_mut(outpos..outpos + mlen)
+                .ok_or(TlgError::IndexOutOfRange)?;
+            for d in dest {
+                let c = text[mpos];
+                *d = c;
+                text[r] = c;
+                r = (r + 1) & 4095;
+                mpos = (mpos + 1) & 4095;
             }
+            outpos += mlen;
         } else {
-            let c = inp[inpos];
+            let c = byte(inpos)?;
             inpos += 1;
-            out[outpos] = c;
+            *out.get_mut(outpos).ok_or(TlgError::IndexOutOfRange)? = c;
             outpos += 1;
             text[r] = c;
-            r += 1;
-            wrapping! {
-                r &= 4095;
+            r = (r + 1) & 4095;
+        }
+    }
+    Ok(r)
+}
+
+/// Decompress a slide stream without knowing the dictionary contents
+/// preceding it, the first byte being written at window position `r`.
+///
+/// Returns the number of bytes written, or `None` if the stream refers to
+/// dictionary bytes it did not write itself or does not fit in `out`.
+#[cfg(feature = "parallel")]
+pub fn tlg5_decompress_slide_independent(out: &mut [u8], inp: &[u8], r: usize) -> Option<usize> {
+    let mut flags = 0u32;
+    let mut inpos = 0usize;
+    let mut outpos = 0usize;
+    while inpos < inp.len() {
+        flags >>= 1;
+        if flags & 256 == 0 {
+            flags = *inp.get(inpos)? as u32 | 0xff00;
+            inpos += 1;
+        }
+        if flags & 1 != 0 {
+            let lo = *inp.get(inpos)? as usize;
+            let hi = *inp.get(inpos + 1)? as usize;
+            let mut mpos = lo | ((hi & 0xf) << 8);
+            let mut mlen = ((hi & 0xf0) >> 4) + 3;
+            inpos += 2;
+            if mlen == 18 {
+                mlen += *inp.get(inpos)? as usize;
+                inpos += 1;
             }
+            for _ in 0..mlen {
+                // out[j] was written at window position (r + j) & 4095; find
+                // the latest such j before the current position.
+                let back = mpos.wrapping_sub(r) & 4095;
+                if back >= outpos {
+                    return None;
+                }
+                let j = back + (outpos - 1 - back) / 4096 * 4096;
+                *out.get_mut(outpos)? = out[j];
+                outpos += 1;
+                mpos = (mpos + 1) & 4095;
+            }
+        } else {
+            *out.get_mut(outpos)? = *inp.get(inpos)?;
+            inpos += 1;
+            outpos += 1;
         }
     }
-    r
+    Some(outpos)
 }
 
 pub fn tlg6_fetch_32bits(data: &[u8], loc: usize) -> Result<u32> {
@@ -207,10 +266,14 @@ pub fn tlg6_decode_golomb_values(
     is_first: bool,
     c: u8,
 ) -> Result<()> {
+    if pixelbuf.len() < pixel_count {
+        return Err(TlgError::IndexOutOfRange);
+    }
     let mut n = TLG6_GOLOMB_N_COUNT - 1;
     let mut a = 0;
     let mut bit_pos = 1;
-    let mut zero = if bit_pool[0] & 1 != 0 { 0u8 } else { 1 };
+    let first = *bit_pool.first().ok_or(TlgError::IndexOutOfRange)?;
+    let mut zero = if first & 1 != 0 { 0u8 } else { 1 };
     let mut index = 0;
     let mut bit_pool_index = 0;
     while index < pixel_count {
@@ -232,8 +295,14 @@ pub fn tlg6_decode_golomb_values(
             bit_pool_index += bit_pos as usize >> 3;
             bit_pos &= 7;
             bit_count -= 1;
+            if bit_count >= 32 {
+                return Err(TlgError::IndexOutOfRange);
+            }
             count = 1 << bit_count;
             count += (tlg6_fetch_32bits(bit_pool, bit_pool_index)? >> bit_pos) & (count - 1);
+            if count as usize > pixel_count - index {
+                return Err(TlgError::IndexOutOfRange);
+            }
             bit_pos += bit_count;
             bit_pool_index += bit_pos as usize >> 3;
             bit_pos &= 7;
@@ -256,12 +325,13 @@ pub fn tlg6_decode_golomb_values(
             zero ^= 1;
         } else {
             loop {
-                let k = TLG6_GOLOMB_BIT_LENGTH_TABLE[a][n];
+                let k = TLG6_GOLOMB_BIT_LENGTH_TABLE
+                    .get(a)
+                    .ok_or(TlgError::IndexOutOfRange)?[n];
                 let mut t = tlg6_fetch_32bits(bit_pool, bit_pool_index)? >> bit_pos;
                 let mut bit_count;
                 let mut b;
                 let mut v;
-                let sign;
                 if t != 0 {
                     b = TLG6_LEADING_ZERO_TABLE[(t as usize) & (TLG6_LEADING_ZERO_TABLE_SIZE - 1)];
                     bit_count = b as i32;
@@ -278,17 +348,15 @@ pub fn tlg6_decode_golomb_values(
                     bit_count -= 1;
                 } else {
                     bit_pool_index += 5;
-                    bit_count = if bit_pool_index == 0 {
-                        return Err(TlgError::IndexOutOfRange);
-                    } else {
-                        bit_pool[bit_pool_index - 1] as i32
-                    };
+                    bit_count = *bit_pool
+                        .get(bit_pool_index - 1)
+                        .ok_or(TlgError::IndexOutOfRange)? as i32;
                     bit_pos = 0;
                     t = tlg6_fetch_32bits(bit_pool, bit_pool_index)?;
                     b = 0;
                 }
                 v = (bit_count << k) + ((t as i32 >> b) & ((1 << k) - 1));
-                sign = (v & 1) - 1;
+                let sign = (v & 1) - 1;
                 v >>= 1;
                 a += v as usize;
                 if is_first {
@@ -369,6 +437,7 @@ fn cal_v(b: u8, g: u8, r: u8, a: u8) -> u32 {
         + ((a as u32) << 24)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn tlg6_decode_line(
     prevline: &[u32],
     curline: &mut [u32],
@@ -378,44 +447,63 @@ pub fn tlg6_decode_line(
     filter_types: &[u8],
     skipblockbytes: usize,
     inp: &[u32],
-    mut inp_pos: usize,
+    inp_pos: usize,
     initialp: u32,
     oddskip: isize,
     dir: bool,
 ) -> Result<()> {
     let mut p;
     let mut up;
-    let step: i32;
     let mut prevline_pos = 0;
     let mut curline_pos = 0;
     if start_block != 0 {
         prevline_pos += start_block * TLG6_W_BLOCK_SIZE;
         curline_pos += start_block * TLG6_W_BLOCK_SIZE;
-        p = curline[curline_pos - 1];
-        up = prevline[prevline_pos - 1];
+        p = *curline
+            .get(curline_pos - 1)
+            .ok_or(TlgError::IndexOutOfRange)?;
+        up = *prevline
+            .get(prevline_pos - 1)
+            .ok_or(TlgError::IndexOutOfRange)?;
     } else {
         p = initialp;
         up = initialp;
     }
-    inp_pos += skipblockbytes * start_block;
-    step = if dir { 1 } else { -1 };
-    for i in start_block..block_limit {
-        let mut w = (width as usize - i * TLG6_W_BLOCK_SIZE).min(TLG6_W_BLOCK_SIZE);
-        let ww = w;
+    // Right to left lines step one before the start of their block, so the
+    // position is kept signed and only checked when read.
+    let skipblockbytes = skipblockbytes as isize;
+    let mut inp_pos = inp_pos as isize + skipblockbytes * start_block as isize;
+    let step: isize = if dir { 1 } else { -1 };
+    for (i, &filter_type) in filter_types
+        .iter()
+        .enumerate()
+        .take(block_limit)
+        .skip(start_block)
+    {
+        let mut w = (width as usize)
+            .checked_sub(i * TLG6_W_BLOCK_SIZE)
+            .ok_or(TlgError::IndexOutOfRange)?
+            .min(TLG6_W_BLOCK_SIZE);
+        let ww = w as isize;
         if step == -1 {
             inp_pos += ww - 1;
         }
         if i & 1 != 0 {
-            inp_pos = (inp_pos as isize + oddskip as isize * ww as isize) as usize;
+            inp_pos += oddskip * ww;
         }
         loop {
-            let inpt = inp[inp_pos];
+            let inpt = *usize::try_from(inp_pos)
+                .ok()
+                .and_then(|pos| inp.get(pos))
+                .ok_or(TlgError::IndexOutOfRange)?;
             let ia = ((inpt >> 24) & 0xFF) as u8;
             let ir = ((inpt >> 16) & 0xFF) as u8;
             let ig = ((inpt >> 8) & 0xFF) as u8;
             let ib = (inpt & 0xFF) as u8;
-            let u = prevline[prevline_pos];
-            p = match filter_types[i] {
+            let u = *prevline
+                .get(prevline_pos)
+                .ok_or(TlgError::IndexOutOfRange)?;
+            p = match filter_type {
                 0 => med(p, u, up, cal_v(ib, ig, ir, ia)),
                 1 => avg(p, u, cal_v(ib, ig, ir, ia)),
                 2 => med(
@@ -585,14 +673,16 @@ pub fn tlg6_decode_line(
                     cal_v(ib, wrapping!(ig + (ib << 1)), wrapping!(ir + (ib << 1)), ia),
                 ),
                 v => {
-                    return Err(TlgError::Str(format!("Unsupported filter type: {}", v)));
+                    return Err(TlgError::UnsupportedFilterType(v));
                 }
             };
             up = u;
-            curline[curline_pos] = p;
+            *curline
+                .get_mut(curline_pos)
+                .ok_or(TlgError::IndexOutOfRange)? = p;
             curline_pos += 1;
             prevline_pos += 1;
-            inp_pos = (inp_pos as isize + step as isize) as usize;
+            inp_pos += step;
             w -= 1;
             if w == 0 {
                 break;
@@ -604,7 +694,7 @@ pub fn tlg6_decode_line(
             inp_pos += skipblockbytes + 1;
         }
         if i & 1 != 0 {
-            inp_pos = (inp_pos as isize - oddskip as isize * ww as isize) as usize;
+            inp_pos -= oddskip * ww;
         }
     }
     Ok(())
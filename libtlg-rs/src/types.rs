@@ -1,4 +1,5 @@
-use std::{collections::HashMap, hash::Hash};
+use crate::*;
+use core::hash::Hash;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 /// TLG Color Type
@@ -11,11 +12,29 @@ pub enum TlgColorType {
     Bgra32,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// Chunk of a TLG0.0 SDS file
+///
+/// The content of `tags` chunks is parsed into [`Tlg::tags`]. A chunk named
+/// `tags` only marks where such a chunk was, and its data is the number of
+/// tags it held as a little-endian `u32`. [`save_tlg`](crate::save_tlg)
+/// writes that many tags there, and all remaining tags at the last marker.
+/// Without a marker, the tags are written before the other chunks.
+pub struct TlgChunk {
+    /// Chunk name
+    pub name: [u8; 4],
+    /// Chunk payload
+    pub data: Vec<u8>,
+}
+
 #[derive(Debug, Clone)]
 /// TLG Image
 pub struct Tlg {
-    /// Tag dictionary
-    pub tags: HashMap<Vec<u8>, Vec<u8>>,
+    /// Tags, in file order
+    pub tags: TlgTags,
+    /// SDS chunks other than tags, and markers of the `tags` chunks, in file
+    /// order
+    pub chunks: Vec<TlgChunk>,
     /// TLG Version: 0=unknown, 5=v5, 6=v6
     pub version: u32,
     /// Image width
@@ -26,13 +45,181 @@ pub struct Tlg {
     pub color: TlgColorType,
     /// Image data
     pub data: Vec<u8>,
+    /// Layout of the TLG5 stream the image was decoded from, used by
+    /// [`save_tlg`](crate::save_tlg) to reproduce it. Only recorded if
+    /// [`DecodeOptions::keep_tlg5_layout`] is set.
+    pub tlg5_layout: Option<Tlg5Layout>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// Encoder choices of a TLG5 stream
+pub struct Tlg5Layout {
+    /// Block height, as stored in the header
+    pub block_height: u32,
+    /// Whether each channel of each block is stored without compression,
+    /// in file order
+    pub raw: Vec<bool>,
+}
+
+#[derive(Debug, Clone)]
+/// TLG image information, read without decoding pixel data
+pub struct TlgInfo {
+    /// Tags, in file order
+    pub tags: TlgTags,
+    /// SDS chunks other than tags, and markers of the `tags` chunks, in file
+    /// order
+    pub chunks: Vec<TlgChunk>,
+    /// TLG Version: 5=v5, 6=v6
+    pub version: u32,
+    /// Image width
+    pub width: u32,
+    /// Image height
+    pub height: u32,
+    /// Color type
+    pub color: TlgColorType,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+/// Pixel layout of decoded image data
+pub enum PixelFormat {
+    /// Layout of the color type of the image: grayscale, BGR or BGRA
+    #[default]
+    Native,
+    /// RGBA 8-bit
+    Rgba8,
+    /// RGB 8-bit, dropping alpha
+    Rgb8,
+    /// BGRA 8-bit
+    Bgra8,
+    /// BGRX 8-bit, with the unused byte set to 255
+    Bgrx8,
+    /// RGBA 8-bit with color premultiplied by alpha
+    Rgba8Premultiplied,
+    /// Grayscale and alpha 8-bit, converting color with BT.601 luma weights
+    GrayAlpha8,
+}
+
+impl PixelFormat {
+    /// Number of bytes per pixel when decoding an image of color type `color`.
+    pub fn bytes_per_pixel(self, color: TlgColorType) -> usize {
+        match self {
+            PixelFormat::Native => match color {
+                TlgColorType::Grayscale8 => 1,
+                TlgColorType::Bgr24 => 3,
+                TlgColorType::Bgra32 => 4,
+            },
+            PixelFormat::Rgb8 => 3,
+            PixelFormat::GrayAlpha8 => 2,
+            PixelFormat::Rgba8
+            | PixelFormat::Bgra8
+            | PixelFormat::Bgrx8
+            | PixelFormat::Rgba8Premultiplied => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Resource limits applied while decoding
+///
+/// Exceeding a limit fails with [`TlgError::LimitExceeded`]. By default,
+/// buffers are limited to 512 MiB, and the other limits are unlimited.
+pub struct DecodeLimits {
+    /// Maximum number of pixels of the image
+    pub max_pixels: u64,
+    /// Maximum size in bytes of a single buffer allocated by the decoder
+    pub max_alloc_bytes: usize,
+    /// Maximum total size in bytes of the `tags` chunks
+    pub max_tag_bytes: usize,
+    /// Maximum number of chunks read from a TLG0.0 SDS file
+    pub max_chunks: usize,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_pixels: u64::MAX,
+            max_alloc_bytes: 512 * 1024 * 1024,
+            max_tag_bytes: usize::MAX,
+            max_chunks: usize::MAX,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Options for decoding TLG images
+pub struct DecodeOptions {
+    /// Pixel layout of the decoded data
+    pub pixel_format: PixelFormat,
+    /// Resource limits
+    pub limits: DecodeLimits,
+    /// Record the [`Tlg5Layout`] of TLG5 images in [`Tlg::tlg5_layout`]
+    pub keep_tlg5_layout: bool,
+}
+
+#[cfg(feature = "encode")]
+#[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
+#[derive(Debug, Clone, Default)]
+/// Options for encoding TLG images
+pub struct EncodeOptions {
+    /// TLG5 encoder options
+    pub tlg5: Tlg5EncodeOptions,
+    /// Effort spent compressing the TLG6 filter table
+    pub tlg6_filter_effort: LzssEffort,
+}
+
+#[cfg(feature = "encode")]
+#[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Options for encoding TLG5 images
+///
+/// Ignored when the image has a [`Tlg::tlg5_layout`]: the block height and
+/// the storage of each channel are taken from the layout, and
+/// [`LzssEffort::Normal`] is used, so that a stream written by the original
+/// encoder is reproduced byte for byte.
+pub struct Tlg5EncodeOptions {
+    /// Number of rows in a block. Taller blocks compress better, shorter ones
+    /// use less memory when decoding one block at a time.
+    pub block_height: u32,
+    /// Store every channel without LZSS compression
+    pub force_raw: bool,
+    /// Effort spent looking for LZSS matches
+    pub effort: LzssEffort,
+}
+
+#[cfg(feature = "encode")]
+impl Default for Tlg5EncodeOptions {
+    fn default() -> Self {
+        Self {
+            block_height: 4,
+            force_raw: false,
+            effort: LzssEffort::default(),
+        }
+    }
+}
+
+#[cfg(feature = "encode")]
+#[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+/// Effort spent by the LZSS compressor
+pub enum LzssEffort {
+    /// Only try the most recent candidates for each match
+    Fast,
+    /// Try every candidate for each match, as the original encoder does
+    #[default]
+    Normal,
+    /// Choose the literals and matches giving the smallest output
+    Optimal,
 }
 
 #[derive(Debug)]
 /// TLG Error
 pub enum TlgError {
+    #[cfg(feature = "std")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "std")))]
     /// IO Error
     Io(std::io::Error),
+    /// The data ended before the end of the image
+    UnexpectedEof,
     /// Invalid TLG format
     InvalidFormat,
     /// Unsupported color type
@@ -41,6 +228,19 @@ pub enum TlgError {
     IndexOutOfRange,
     /// Unsupported compressed method
     UnsupportedCompressedMethod(u8),
+    /// Unsupported TLG6 data flags
+    UnsupportedDataFlags(u8),
+    /// Unsupported TLG6 color types
+    UnsupportedColorTypes(u8),
+    /// Unsupported TLG6 filter type
+    UnsupportedFilterType(u8),
+    /// TLG6 external golomb bit length table, which no reference decoder
+    /// reads
+    UnsupportedGolombTable,
+    /// A [`DecodeLimits`] limit was exceeded, named by the field
+    LimitExceeded(&'static str),
+    /// Tag value that cannot be parsed, named by the key
+    InvalidTagValue(Vec<u8>),
     /// String type error
     Str(String),
     #[cfg(feature = "encode")]
@@ -49,16 +249,28 @@ pub enum TlgError {
     EncodeError(String),
 }
 
-impl std::fmt::Display for TlgError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Display for TlgError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
+            #[cfg(feature = "std")]
             TlgError::Io(e) => write!(f, "IO Error: {}", e),
+            TlgError::UnexpectedEof => write!(f, "Unexpected end of data"),
             TlgError::InvalidFormat => write!(f, "Invalid TLG format"),
             TlgError::UnsupportedColorType(c) => write!(f, "Unsupported color type: {}", c),
             TlgError::IndexOutOfRange => write!(f, "Index out of range"),
             TlgError::UnsupportedCompressedMethod(m) => {
                 write!(f, "Unsupported compressed method: {}", m)
             }
+            TlgError::UnsupportedDataFlags(d) => write!(f, "Unsupported data flags: {}", d),
+            TlgError::UnsupportedColorTypes(c) => write!(f, "Unsupported color types: {}", c),
+            TlgError::UnsupportedFilterType(t) => write!(f, "Unsupported filter type: {}", t),
+            TlgError::UnsupportedGolombTable => {
+                write!(f, "External golomb bit length table is not supported")
+            }
+            TlgError::LimitExceeded(l) => write!(f, "Decode limit exceeded: {}", l),
+            TlgError::InvalidTagValue(k) => {
+                write!(f, "Invalid value of tag {}", String::from_utf8_lossy(k))
+            }
             TlgError::Str(s) => write!(f, "{}", s),
             #[cfg(feature = "encode")]
             TlgError::EncodeError(s) => write!(f, "Encoding error: {}", s),
@@ -66,6 +278,7 @@ impl std::fmt::Display for TlgError {
     }
 }
 
+#[cfg(feature = "std")]
 impl From<std::io::Error> for TlgError {
     fn from(err: std::io::Error) -> Self {
         TlgError::Io(err)
@@ -90,4 +303,4 @@ impl From<&String> for TlgError {
     }
 }
 
-impl std::error::Error for TlgError {}
+impl core::error::Error for TlgError {}
@@ -0,0 +1,136 @@
+//! JavaScript bindings, built with `wasm-bindgen`.
+//!
+//! Build a module for the browser with
+//! `cargo rustc -p libtlg-rs --release --target wasm32-unknown-unknown --features wasm-bindgen --crate-type cdylib`
+//! and run the `wasm-bindgen` CLI on the output.
+use crate::*;
+use js_sys::{Array, Uint8ClampedArray};
+use std::io::Cursor;
+use wasm_bindgen::prelude::*;
+
+/// Information of a TLG image, exported to JavaScript as `TlgInfo`.
+#[wasm_bindgen(js_name = TlgInfo)]
+#[cfg_attr(docsrs, doc(cfg(feature = "wasm-bindgen")))]
+pub struct WasmTlgInfo {
+    info: TlgInfo,
+}
+
+#[wasm_bindgen(js_class = TlgInfo)]
+impl WasmTlgInfo {
+    /// TLG version: 5 or 6
+    #[wasm_bindgen(getter)]
+    pub fn version(&self) -> u32 {
+        self.info.version
+    }
+
+    /// Image width
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.info.width
+    }
+
+    /// Image height
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        self.info.height
+    }
+
+    /// Color type of the file: `grayscale8`, `bgr24` or `bgra32`
+    #[wasm_bindgen(getter, js_name = colorType)]
+    pub fn color_type(&self) -> String {
+        match self.info.color {
+            TlgColorType::Grayscale8 => "grayscale8",
+            TlgColorType::Bgr24 => "bgr24",
+            TlgColorType::Bgra32 => "bgra32",
+        }
+        .to_string()
+    }
+
+    /// Tags as an array of `[key, value]` strings in file order, decoded as
+    /// UTF-8 with invalid sequences replaced.
+    #[wasm_bindgen(getter)]
+    pub fn tags(&self) -> Array {
+        self.info
+            .tags
+            .iter()
+            .map(|(k, v)| {
+                Array::of2(
+                    &String::from_utf8_lossy(k).as_ref().into(),
+                    &String::from_utf8_lossy(v).as_ref().into(),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Decoded TLG image, exported to JavaScript as `TlgImage`.
+#[wasm_bindgen(js_name = TlgImage)]
+#[cfg_attr(docsrs, doc(cfg(feature = "wasm-bindgen")))]
+pub struct WasmTlgImage {
+    info: TlgInfo,
+    data: Vec<u8>,
+}
+
+#[wasm_bindgen(js_class = TlgImage)]
+impl WasmTlgImage {
+    /// Image width
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.info.width
+    }
+
+    /// Image height
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        self.info.height
+    }
+
+    /// Image information and tags
+    #[wasm_bindgen(getter)]
+    pub fn info(&self) -> WasmTlgInfo {
+        WasmTlgInfo {
+            info: self.info.clone(),
+        }
+    }
+
+    /// RGBA pixel data, ready for `new ImageData(data, width, height)`
+    #[wasm_bindgen(getter)]
+    pub fn data(&self) -> Uint8ClampedArray {
+        Uint8ClampedArray::from(self.data.as_slice())
+    }
+}
+
+/// Read the information and tags of a TLG image without decoding pixel data.
+///
+/// Exported to JavaScript as `probe`.
+#[wasm_bindgen(js_name = probe)]
+#[cfg_attr(docsrs, doc(cfg(feature = "wasm-bindgen")))]
+pub fn probe_wasm(data: &[u8]) -> std::result::Result<WasmTlgInfo, JsError> {
+    Ok(WasmTlgInfo {
+        info: probe_tlg(Cursor::new(data))?,
+    })
+}
+
+/// Decode a TLG image to RGBA.
+///
+/// Exported to JavaScript as `decode`.
+#[wasm_bindgen(js_name = decode)]
+#[cfg_attr(docsrs, doc(cfg(feature = "wasm-bindgen")))]
+pub fn decode_wasm(data: &[u8]) -> std::result::Result<WasmTlgImage, JsError> {
+    let options = DecodeOptions {
+        pixel_format: PixelFormat::Rgba8,
+        ..Default::default()
+    };
+    let tlg = load_tlg_with_options(Cursor::new(data), &options)?;
+    Ok(WasmTlgImage {
+        info: TlgInfo {
+            tags: tlg.tags,
+            chunks: tlg.chunks,
+            version: tlg.version,
+            width: tlg.width,
+            height: tlg.height,
+            color: tlg.color,
+        },
+        data: tlg.data,
+    })
+}
@@ -0,0 +1,100 @@
+//! Incremental decoding with `TlgDecoder` against whole image decoding.
+#![cfg(feature = "encode")]
+use libtlg_rs::{
+    EncodeOptions, Tlg, TlgColorType, TlgDecoder, TlgTags, load_tlg, save_tlg_with_options,
+};
+use std::io::Cursor;
+
+/// Encoded images of both versions, TLG5 with blocks of 5 rows.
+fn files() -> Vec<Vec<u8>> {
+    let (width, height) = (21u32, 19u32);
+    let data = (0..width * height * 4)
+        .map(|i| (i * 13 / 7 + i % 5) as u8)
+        .collect();
+    let mut img = Tlg {
+        tags: TlgTags::new(),
+        chunks: Vec::new(),
+        version: 5,
+        width,
+        height,
+        color: TlgColorType::Bgra32,
+        data,
+        tlg5_layout: None,
+    };
+    let mut options = EncodeOptions::default();
+    options.tlg5.block_height = 5;
+    let mut files = Vec::new();
+    for version in [5, 6] {
+        img.version = version;
+        let mut buf = Vec::new();
+        save_tlg_with_options(&img, &mut buf, &options).unwrap();
+        files.push(buf);
+    }
+    files
+}
+
+#[test]
+fn next_row() {
+    for file in files() {
+        let expected = load_tlg(Cursor::new(&file)).unwrap().data;
+        let mut decoder = TlgDecoder::new(Cursor::new(&file)).unwrap();
+        let row_size = decoder.row_size();
+        let mut data = Vec::new();
+        while let Some(row) = decoder.next_row().unwrap() {
+            assert_eq!(row.len(), row_size);
+            data.extend_from_slice(row);
+        }
+        assert_eq!(decoder.remaining_rows(), 0);
+        assert!(data == expected);
+    }
+}
+
+#[test]
+fn next_stripe() {
+    for file in files() {
+        let expected = load_tlg(Cursor::new(&file)).unwrap().data;
+        let mut decoder = TlgDecoder::new(Cursor::new(&file)).unwrap();
+        // A row first, so that the first stripe is only partly returned.
+        let mut data = decoder.next_row().unwrap().unwrap().to_vec();
+        while let Some(stripe) = decoder.next_stripe().unwrap() {
+            data.extend_from_slice(stripe);
+        }
+        assert!(data == expected);
+    }
+}
+
+#[test]
+fn read_rows() {
+    for file in files() {
+        let expected = load_tlg(Cursor::new(&file)).unwrap().data;
+        let row_size = TlgDecoder::new(Cursor::new(&file)).unwrap().row_size();
+        // Smaller than both a TLG5 block and a TLG6 stripe, with a partial
+        // row left over.
+        for rows in [1, 3] {
+            let mut decoder = TlgDecoder::new(Cursor::new(&file)).unwrap();
+            let mut buf = vec![0; rows * row_size + row_size / 2];
+            let mut data = Vec::new();
+            loop {
+                let n = decoder.read_rows(&mut buf).unwrap();
+                if n == 0 {
+                    break;
+                }
+                assert!(n <= rows);
+                data.extend_from_slice(&buf[..n * row_size]);
+            }
+            assert!(data == expected, "{rows}");
+        }
+    }
+}
+
+#[test]
+fn read_rows_with_short_buffer() {
+    for file in files() {
+        let mut decoder = TlgDecoder::new(Cursor::new(&file)).unwrap();
+        let mut buf = vec![0; decoder.row_size() - 1];
+        assert!(decoder.read_rows(&mut buf).is_err());
+        assert_eq!(decoder.current_row(), 0);
+        while decoder.next_stripe().unwrap().is_some() {}
+        assert_eq!(decoder.read_rows(&mut buf).unwrap(), 0);
+    }
+}
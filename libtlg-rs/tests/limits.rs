@@ -0,0 +1,127 @@
+//! Resource limits on hostile and oversized input.
+use libtlg_rs::{DecodeOptions, TlgDecoder, TlgError, load_tlg, load_tlg_from_slice};
+use std::io::Cursor;
+
+/// A 384 KB TLG6 file of 2^28 x 1 pixels, whose filter types expand
+/// 88 times from LZSS matches of the maximum length.
+fn wide_tlg6() -> Vec<u8> {
+    let width = 1u32 << 28;
+    let mut data = b"TLG6.0\x00raw\x1a".to_vec();
+    data.extend_from_slice(&[4, 0, 0, 0]);
+    data.extend_from_slice(&width.to_le_bytes());
+    data.extend_from_slice(&1u32.to_le_bytes());
+    data.extend_from_slice(&64u32.to_le_bytes());
+    // Groups of a flag byte and 8 matches of 273 bytes.
+    let groups = (width as usize / 8) / (8 * 273);
+    let mut group = vec![0xff];
+    group.extend_from_slice(&[0x00, 0xf0, 0xff].repeat(8));
+    let filters = group.repeat(groups);
+    data.extend_from_slice(&(filters.len() as u32).to_le_bytes());
+    data.extend_from_slice(&filters);
+    // A 64 bits stream of zeros for each channel.
+    for _ in 0..4 {
+        data.extend_from_slice(&64u32.to_le_bytes());
+        data.extend_from_slice(&[0; 8]);
+    }
+    data
+}
+
+#[test]
+fn wide_tlg6_is_rejected_by_default() {
+    let data = wide_tlg6();
+    assert!(matches!(
+        load_tlg(Cursor::new(&data)),
+        Err(TlgError::LimitExceeded("max_alloc_bytes"))
+    ));
+    assert!(matches!(
+        load_tlg_from_slice(&data, &DecodeOptions::default()),
+        Err(TlgError::LimitExceeded("max_alloc_bytes"))
+    ));
+    assert!(matches!(
+        TlgDecoder::new(Cursor::new(&data)),
+        Err(TlgError::LimitExceeded("max_alloc_bytes"))
+    ));
+}
+
+/// Each limit of [`DecodeLimits`] on its own.
+#[cfg(feature = "encode")]
+mod fields {
+    use super::*;
+    use libtlg_rs::{DecodeLimits, Tlg, TlgChunk, TlgColorType, load_tlg_with_options};
+
+    /// A 16x16 image with 3 chunks, including 15 bytes of tags.
+    fn sds(version: u32) -> Vec<u8> {
+        let mut img = Tlg {
+            tags: Default::default(),
+            chunks: vec![
+                TlgChunk {
+                    name: *b"abcd",
+                    data: vec![1; 8],
+                },
+                TlgChunk {
+                    name: *b"efgh",
+                    data: vec![2; 8],
+                },
+            ],
+            version,
+            width: 16,
+            height: 16,
+            color: TlgColorType::Bgra32,
+            data: vec![0x80; 16 * 16 * 4],
+            tlg5_layout: None,
+        };
+        img.tags.append("mode", "alpha");
+        let mut buf = Vec::new();
+        libtlg_rs::save_tlg(&img, &mut buf).unwrap();
+        buf
+    }
+
+    /// Limits exceeded by [`sds`] for `field` only.
+    fn limits(field: &str) -> DecodeLimits {
+        let mut limits = DecodeLimits::default();
+        match field {
+            "max_pixels" => limits.max_pixels = 255,
+            "max_alloc_bytes" => limits.max_alloc_bytes = 100,
+            "max_tag_bytes" => limits.max_tag_bytes = 14,
+            "max_chunks" => limits.max_chunks = 2,
+            _ => unreachable!(),
+        }
+        limits
+    }
+
+    const FIELDS: [&str; 4] = [
+        "max_pixels",
+        "max_alloc_bytes",
+        "max_tag_bytes",
+        "max_chunks",
+    ];
+
+    #[test]
+    fn each_limit_is_reported() {
+        for version in [5, 6] {
+            let data = sds(version);
+            load_tlg(Cursor::new(&data)).unwrap();
+            for field in FIELDS {
+                let options = DecodeOptions {
+                    limits: limits(field),
+                    ..Default::default()
+                };
+                let result = load_tlg_with_options(Cursor::new(&data), &options);
+                assert!(
+                    matches!(result, Err(TlgError::LimitExceeded(f)) if f == field),
+                    "{version} {field}"
+                );
+                let result = load_tlg_from_slice(&data, &options);
+                assert!(
+                    matches!(result, Err(TlgError::LimitExceeded(f)) if f == field),
+                    "{version} {field}"
+                );
+                let result = TlgDecoder::with_options(Cursor::new(&data), &options);
+                assert!(
+                    matches!(result, Err(TlgError::LimitExceeded(f)) if f == field),
+                    "{version} {field}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! Chunks of TLG0.0 SDS files kept across load and save.
+#![cfg(feature = "encode")]
+use libtlg_rs::{Tlg, TlgColorType, TlgTags, load_tlg, save_tlg};
+use std::io::Cursor;
+
+fn chunk(name: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = name.to_vec();
+    chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(data);
+    chunk
+}
+
+/// A TLG0.0 file wrapping a small TLG5 image, followed by `chunks`.
+fn sds(chunks: &[Vec<u8>]) -> Vec<u8> {
+    let img = Tlg {
+        tags: TlgTags::new(),
+        chunks: Vec::new(),
+        version: 5,
+        width: 3,
+        height: 2,
+        color: TlgColorType::Grayscale8,
+        data: vec![1, 2, 3, 4, 5, 6],
+        tlg5_layout: None,
+    };
+    let mut raw = Vec::new();
+    save_tlg(&img, &mut raw).unwrap();
+    let mut data = b"TLG0.0\x00sds\x1a".to_vec();
+    data.extend_from_slice(&(raw.len() as u32).to_le_bytes());
+    data.extend_from_slice(&raw);
+    data.extend(chunks.concat());
+    data
+}
+
+fn resave(data: &[u8]) -> Vec<u8> {
+    let tlg = load_tlg(Cursor::new(data)).unwrap();
+    let mut out = Vec::new();
+    save_tlg(&tlg, &mut out).unwrap();
+    out
+}
+
+#[test]
+fn chunk_order_is_kept() {
+    let files = [
+        sds(&[chunk(b"foo ", b"x"), chunk(b"tags", b"1:a=1:1,")]),
+        sds(&[
+            chunk(b"tags", b"1:a=1:1,1:a=1:2,"),
+            chunk(b"foo ", b"x"),
+            chunk(b"tags", b""),
+            chunk(b"bar ", b""),
+            chunk(b"tags", b"1:b=2:22,"),
+        ]),
+    ];
+    for data in files {
+        assert!(resave(&data) == data);
+    }
+}
+
+#[test]
+fn new_tags_go_to_the_last_tags_chunk() {
+    let data = sds(&[
+        chunk(b"tags", b"1:a=1:1,"),
+        chunk(b"foo ", b"x"),
+        chunk(b"tags", b"1:b=1:2,"),
+    ]);
+    let mut tlg = load_tlg(Cursor::new(&data)).unwrap();
+    tlg.tags.append("c", "3");
+    let mut out = Vec::new();
+    save_tlg(&tlg, &mut out).unwrap();
+    let expected = sds(&[
+        chunk(b"tags", b"1:a=1:1,"),
+        chunk(b"foo ", b"x"),
+        chunk(b"tags", b"1:b=1:2,1:c=1:3,"),
+    ]);
+    assert!(out == expected);
+}
+
+#[test]
+fn tags_without_marker_come_first() {
+    let data = sds(&[chunk(b"foo ", b"x")]);
+    let mut tlg = load_tlg(Cursor::new(&data)).unwrap();
+    tlg.tags.append("a", "1");
+    let mut out = Vec::new();
+    save_tlg(&tlg, &mut out).unwrap();
+    let expected = sds(&[chunk(b"tags", b"1:a=1:1,"), chunk(b"foo ", b"x")]);
+    assert!(out == expected);
+}
@@ -0,0 +1,68 @@
+//! Reproduction of TLG5 streams from their recorded layout.
+#![cfg(feature = "encode")]
+use libtlg_rs::{
+    DecodeOptions, EncodeOptions, LzssEffort, Tlg, TlgColorType, TlgTags, load_tlg_with_options,
+    save_tlg_with_options,
+};
+use std::io::Cursor;
+
+/// Smooth colors with a noisy alpha channel in the lower half, so that
+/// compressed and raw channels are mixed.
+fn image() -> Tlg {
+    let (width, height) = (23u32, 17u32);
+    let mut x = 0x2545_f491u32;
+    let mut data = Vec::new();
+    for y in 0..height {
+        for px in 0..width {
+            x = x.wrapping_mul(1664525).wrapping_add(1013904223);
+            let alpha = if y >= height / 2 { x >> 24 } else { 255 };
+            data.extend_from_slice(&[(px * 4) as u8, (y * 6) as u8, (px + y) as u8, alpha as u8]);
+        }
+    }
+    Tlg {
+        tags: TlgTags::new(),
+        chunks: Vec::new(),
+        version: 5,
+        width,
+        height,
+        color: TlgColorType::Bgra32,
+        data,
+        tlg5_layout: None,
+    }
+}
+
+fn encode(img: &Tlg, block_height: u32, force_raw: bool, effort: LzssEffort) -> Vec<u8> {
+    let mut options = EncodeOptions::default();
+    options.tlg5.block_height = block_height;
+    options.tlg5.force_raw = force_raw;
+    options.tlg5.effort = effort;
+    let mut buf = Vec::new();
+    save_tlg_with_options(img, &mut buf, &options).unwrap();
+    buf
+}
+
+#[test]
+fn layout_reproduces_stream() {
+    let img = image();
+    let options = DecodeOptions {
+        keep_tlg5_layout: true,
+        ..Default::default()
+    };
+    let mut mixed = false;
+    for block_height in [1, 3, 4, 40] {
+        for force_raw in [false, true] {
+            let original = encode(&img, block_height, force_raw, LzssEffort::Normal);
+            let decoded = load_tlg_with_options(Cursor::new(&original), &options).unwrap();
+            let layout = decoded.tlg5_layout.as_ref().unwrap();
+            assert_eq!(layout.block_height, block_height);
+            assert_eq!(layout.raw.iter().all(|&raw| raw), force_raw);
+            mixed |= layout.raw.contains(&true) && layout.raw.contains(&false);
+            // The layout overrides the block height, storage and effort.
+            for effort in [LzssEffort::Fast, LzssEffort::Normal, LzssEffort::Optimal] {
+                let saved = encode(&decoded, 2, !force_raw, effort);
+                assert!(saved == original, "{block_height} {force_raw} {effort:?}");
+            }
+        }
+    }
+    assert!(mixed);
+}
@@ -0,0 +1,84 @@
+//! Round trips of images through the TLG6 encoder and the decoder.
+#![cfg(feature = "encode")]
+use libtlg_rs::{
+    DecodeOptions, Tlg, TlgColorType, TlgError, TlgTags, load_tlg, load_tlg_from_slice, save_tlg,
+};
+use std::io::Cursor;
+
+/// Sizes around the 8x8 block size of TLG6.
+const SIZES: [(u32, u32); 7] = [(1, 1), (7, 5), (8, 8), (9, 16), (13, 21), (64, 3), (33, 67)];
+
+/// Gradients with noise in some blocks, so that several filters are used.
+fn image(width: u32, height: u32, color: TlgColorType) -> Tlg {
+    let colors = match color {
+        TlgColorType::Grayscale8 => 1,
+        TlgColorType::Bgr24 => 3,
+        TlgColorType::Bgra32 => 4,
+    };
+    let mut x = 0x1234_5678u32;
+    let mut data = Vec::new();
+    for y in 0..height {
+        for px in 0..width {
+            for c in 0..colors {
+                x = x.wrapping_mul(1664525).wrapping_add(1013904223);
+                let noise = if (px / 8 + y / 8) % 3 == 0 {
+                    x >> 24
+                } else {
+                    0
+                };
+                data.push((px * 5 + y * 3 + c * 40 + noise) as u8);
+            }
+        }
+    }
+    Tlg {
+        tags: TlgTags::new(),
+        chunks: Vec::new(),
+        version: 6,
+        width,
+        height,
+        color,
+        data,
+        tlg5_layout: None,
+    }
+}
+
+fn round_trip(color: TlgColorType) {
+    for (width, height) in SIZES {
+        let img = image(width, height, color);
+        let mut buf = Vec::new();
+        save_tlg(&img, &mut buf).unwrap();
+        let decoded = load_tlg(Cursor::new(&buf)).unwrap();
+        assert_eq!(decoded.version, 6);
+        assert_eq!((decoded.width, decoded.height), (width, height));
+        assert_eq!(decoded.color, color);
+        assert!(decoded.data == img.data, "{width}x{height} differs");
+    }
+}
+
+#[test]
+fn grayscale8() {
+    round_trip(TlgColorType::Grayscale8);
+}
+
+#[test]
+fn bgr24() {
+    round_trip(TlgColorType::Bgr24);
+}
+
+#[test]
+fn bgra32() {
+    round_trip(TlgColorType::Bgra32);
+}
+
+#[test]
+fn truncated() {
+    let mut buf = Vec::new();
+    save_tlg(&image(33, 67, TlgColorType::Bgra32), &mut buf).unwrap();
+    for len in [0, 11, 30, buf.len() / 2, buf.len() - 1] {
+        let data = &buf[..len];
+        let from_reader = load_tlg(Cursor::new(data));
+        let from_slice = load_tlg_from_slice(data, &DecodeOptions::default());
+        assert!(matches!(from_reader, Err(TlgError::UnexpectedEof)), "{len}");
+        assert!(matches!(from_slice, Err(TlgError::UnexpectedEof)), "{len}");
+    }
+}
@@ -8,6 +8,40 @@ pub struct Arg {
     pub input: String,
     /// Path to the output TLG/PNG file.
     pub output: Option<String>,
+    #[cfg(feature = "encode")]
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..))]
+    /// Number of rows in a TLG5 block.
+    pub block_height: u32,
+    #[cfg(feature = "encode")]
+    #[arg(long)]
+    /// Store TLG5 blocks without compression.
+    pub raw: bool,
+    #[cfg(feature = "encode")]
+    #[arg(long, value_enum, default_value_t = Effort::Normal)]
+    /// Effort spent on TLG5 compression.
+    pub effort: Effort,
+}
+
+#[cfg(feature = "encode")]
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum Effort {
+    /// Only try the most recent candidates for each match.
+    Fast,
+    /// Try every candidate for each match.
+    Normal,
+    /// Choose the literals and matches giving the smallest output.
+    Optimal,
+}
+
+#[cfg(feature = "encode")]
+impl From<Effort> for libtlg_rs::LzssEffort {
+    fn from(effort: Effort) -> Self {
+        match effort {
+            Effort::Fast => libtlg_rs::LzssEffort::Fast,
+            Effort::Normal => libtlg_rs::LzssEffort::Normal,
+            Effort::Optimal => libtlg_rs::LzssEffort::Optimal,
+        }
+    }
 }
 
 impl Arg {
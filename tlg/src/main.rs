@@ -3,26 +3,6 @@ mod arg;
 use std::io::BufRead;
 use std::io::{Seek, Write};
 
-fn convert_bgr_to_rgb(data: &mut libtlg_rs::Tlg) {
-    match data.color {
-        libtlg_rs::TlgColorType::Bgra32 => {
-            for i in (0..data.data.len()).step_by(4) {
-                let b = data.data[i];
-                data.data[i] = data.data[i + 2];
-                data.data[i + 2] = b; // Swap red and blue
-            }
-        }
-        libtlg_rs::TlgColorType::Bgr24 => {
-            for i in (0..data.data.len()).step_by(3) {
-                let b = data.data[i];
-                data.data[i] = data.data[i + 2];
-                data.data[i + 2] = b; // Swap red and blue
-            }
-        }
-        _ => {}
-    }
-}
-
 fn get_relative_path(input: &str, ext: &str) -> String {
     let mut pb = std::path::PathBuf::from(input);
     pb.set_extension(ext);
@@ -34,12 +14,24 @@ fn main() {
     let file = std::fs::File::open(&args.input).expect("Failed to open input file");
     let mut file = std::io::BufReader::new(file);
     if libtlg_rs::check_tlg(&mut file).expect("Failed to check TLG format") {
-        let mut tlg = libtlg_rs::load_tlg(&mut file).expect("Failed to load TLG file");
+        let info = libtlg_rs::probe_tlg(&mut file).expect("Failed to read TLG header");
+        let pixel_format = match info.color {
+            libtlg_rs::TlgColorType::Bgra32 => libtlg_rs::PixelFormat::Rgba8,
+            libtlg_rs::TlgColorType::Bgr24 => libtlg_rs::PixelFormat::Rgb8,
+            libtlg_rs::TlgColorType::Grayscale8 => libtlg_rs::PixelFormat::Native,
+        };
+        let stride = info.width as usize * pixel_format.bytes_per_pixel(info.color);
+        let mut data = vec![0u8; stride * info.height as usize];
+        let options = libtlg_rs::DecodeOptions {
+            pixel_format,
+            ..Default::default()
+        };
+        let tlg = libtlg_rs::load_tlg_into_with_options(&mut file, &mut data, stride, &options)
+            .expect("Failed to load TLG file");
         let output = match &args.output {
             Some(output) => output.clone(),
             None => get_relative_path(&args.input, "png"),
         };
-        convert_bgr_to_rgb(&mut tlg);
         let mut output_file = std::fs::File::create(&output).expect("Failed to create output file");
         let mut encoder = png::Encoder::new(&mut output_file, tlg.width, tlg.height);
         encoder.set_color(match tlg.color {
@@ -50,18 +42,18 @@ fn main() {
         encoder.set_depth(png::BitDepth::Eight);
         let mut writer = encoder.write_header().expect("Failed to write PNG header");
         writer
-            .write_image_data(&tlg.data)
+            .write_image_data(&data)
             .expect("Failed to write PNG image data");
         if !tlg.tags.is_empty() {
             let mut tags_file = std::fs::File::create(get_relative_path(&output, "tags"))
                 .expect("Failed to create tags file");
             for (key, value) in &tlg.tags {
-                tags_file.write_all(&key).expect("Failed to write tag key");
+                tags_file.write_all(key).expect("Failed to write tag key");
                 tags_file
                     .write_all(b"=")
                     .expect("Failed to write tag separator");
                 tags_file
-                    .write_all(&value)
+                    .write_all(value)
                     .expect("Failed to write tag value");
                 tags_file.write_all(b"\n").expect("Failed to write newline");
             }
@@ -88,7 +80,7 @@ fn main() {
             reader
                 .next_frame(&mut data)
                 .expect("Failed to read PNG frame");
-            let mut tags = std::collections::HashMap::new();
+            let mut tags = libtlg_rs::TlgTags::new();
             let tags_path = get_relative_path(&args.input, "tags");
             if std::path::Path::new(&tags_path).exists() {
                 let tags_file = std::fs::File::open(&tags_path).expect("Failed to open tags file");
@@ -102,27 +94,38 @@ fn main() {
                     if let Some(eq_pos) = line.find('=') {
                         let key = line[..eq_pos].trim().as_bytes().to_vec();
                         let value = line[eq_pos + 1..].trim().as_bytes().to_vec();
-                        tags.insert(key, value);
+                        tags.append(key, value);
                     }
                     line.clear();
                 }
             }
             let mut tlg = libtlg_rs::Tlg {
                 tags,
+                chunks: Vec::new(),
                 version: 5,
                 width,
                 height,
                 color: color_type,
                 data,
+                tlg5_layout: None,
             };
-            convert_bgr_to_rgb(&mut tlg);
+            libtlg_rs::swap_red_blue(&mut tlg.data, tlg.color);
             let output = match &args.output {
                 Some(output) => output.clone(),
                 None => get_relative_path(&args.input, "tlg"),
             };
             let mut output_file =
                 std::fs::File::create(&output).expect("Failed to create output file");
-            libtlg_rs::save_tlg(&tlg, &mut output_file).expect("Failed to save TLG file");
+            let options = libtlg_rs::EncodeOptions {
+                tlg5: libtlg_rs::Tlg5EncodeOptions {
+                    block_height: args.block_height,
+                    force_raw: args.raw,
+                    effort: args.effort.into(),
+                },
+                ..Default::default()
+            };
+            libtlg_rs::save_tlg_with_options(&tlg, &mut output_file, &options)
+                .expect("Failed to save TLG file");
         }
     }
 }